@@ -0,0 +1,331 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use crate::cli::{XQuoAuditFormat, XQuoNormalize};
+use crate::normalize::is_normalized_record;
+
+// KINDS と同じ順番で定義し、集計の index として使う.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Newline,
+    Control,
+    LeadingDash,
+    LeadingSpace,
+    TrailingSpace,
+    Glob,
+    InvalidUtf8,
+    Bidi,
+    Confusable,
+    NotNormalized,
+}
+
+const KINDS: &[Kind] = &[
+    Kind::Newline,
+    Kind::Control,
+    Kind::LeadingDash,
+    Kind::LeadingSpace,
+    Kind::TrailingSpace,
+    Kind::Glob,
+    Kind::InvalidUtf8,
+    Kind::Bidi,
+    Kind::Confusable,
+    Kind::NotNormalized,
+];
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Newline => "newline",
+            Kind::Control => "control",
+            Kind::LeadingDash => "leading-dash",
+            Kind::LeadingSpace => "leading-space",
+            Kind::TrailingSpace => "trailing-space",
+            Kind::Glob => "glob",
+            Kind::InvalidUtf8 => "invalid-utf8",
+            Kind::Bidi => "bidi",
+            Kind::Confusable => "confusable",
+            Kind::NotNormalized => "not-normalized",
+        }
+    }
+
+    fn from_name(name: &str) -> Result<Kind> {
+        KINDS
+            .iter()
+            .find(|kind| kind.name() == name)
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<&str> = KINDS.iter().map(|kind| kind.name()).collect();
+                anyhow!(
+                    "unknown audit kind: {} (possible values: {})",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+fn is_bidi_control(c: char) -> bool {
+    matches!(c, '\u{061c}' | '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+// ASCII の文字と見分けにくい文字(キリル文字、ギリシャ文字、全角英数など).
+fn is_confusable(c: char) -> bool {
+    matches!(
+        c,
+        // Cyrillic
+        'а' | 'в' | 'е' | 'к' | 'м' | 'н' | 'о' | 'р' | 'с' | 'т' | 'у' | 'х' | 'ѕ' | 'і' | 'ј' | 'ԁ' | 'һ'
+        | 'А' | 'В' | 'Е' | 'К' | 'М' | 'Н' | 'О' | 'Р' | 'С' | 'Т' | 'Х' | 'Ѕ' | 'І' | 'Ј'
+        // Greek
+        | 'ο' | 'ν' | 'α' | 'ι' | 'κ' | 'ρ'
+        | 'Α' | 'Β' | 'Ε' | 'Ζ' | 'Η' | 'Ι' | 'Κ' | 'Μ' | 'Ν' | 'Ο' | 'Ρ' | 'Τ' | 'Υ' | 'Χ'
+        // slashes and dots
+        | '\u{2044}' | '\u{2215}' | '\u{29f8}' | '\u{2024}' | '\u{ff0e}' | '\u{ff0f}'
+        // fullwidth ASCII
+        | '\u{ff01}'..='\u{ff5e}'
+    )
+}
+
+// 1 行を検査し、該当する種類を KINDS の順番で返す.
+pub fn check(record: &[u8]) -> Vec<Kind> {
+    let (text, invalid_utf8) = match std::str::from_utf8(record) {
+        Ok(text) => (Cow::Borrowed(text), false),
+        Err(_) => (String::from_utf8_lossy(record), true),
+    };
+    let mut kinds = Vec::<Kind>::new();
+    for kind in KINDS {
+        let found = match kind {
+            Kind::Newline => text.contains('\n'),
+            Kind::Control => text.chars().any(|c| c != '\n' && c.is_control()),
+            Kind::LeadingDash => text.starts_with('-'),
+            Kind::LeadingSpace => text.starts_with(' '),
+            Kind::TrailingSpace => text.ends_with(' '),
+            Kind::Glob => text.contains(['*', '?', '[']),
+            Kind::InvalidUtf8 => invalid_utf8,
+            Kind::Bidi => text.chars().any(is_bidi_control),
+            // 他の言語の文字だけで構成されている場合は対象外とし、ASCII の英字と混在している場合のみ報告する.
+            Kind::Confusable => {
+                text.chars().any(is_confusable) && text.chars().any(|c| c.is_ascii_alphabetic())
+            }
+            // 正規化形式は Audit 側で指定されたものと比較する.
+            Kind::NotNormalized => false,
+        };
+        if found {
+            kinds.push(*kind);
+        }
+    }
+    kinds
+}
+
+// 端末などにそのまま出力しても安全な形式で行を表示する.
+pub fn display(record: &[u8]) -> String {
+    let mut ret = String::new();
+    for chunk in record.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c == '"' || c == '\\' || c.is_control() || is_bidi_control(c) {
+                ret.extend(c.escape_default());
+            } else if c == '\'' {
+                ret.push(c);
+            } else {
+                ret.extend(c.escape_debug());
+            }
+        }
+        for b in chunk.invalid() {
+            ret.push_str(&format!("\\x{:02x}", b));
+        }
+    }
+    ret
+}
+
+#[derive(Serialize)]
+struct Finding {
+    index: usize,
+    kinds: Vec<&'static str>,
+    record: String,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    records: usize,
+    counts: BTreeMap<&'static str, usize>,
+    findings: &'a [Finding],
+    violations: Vec<String>,
+}
+
+pub struct Audit<'a> {
+    normalize: &'a XQuoNormalize,
+    max: Vec<(Kind, usize)>,
+    records: usize,
+    counts: Vec<usize>,
+    findings: Vec<Finding>,
+}
+
+impl<'a> Audit<'a> {
+    pub fn new(max: &[(String, usize)], normalize: &'a XQuoNormalize) -> Result<Audit<'a>> {
+        let mut ret = Audit {
+            normalize,
+            // 正規化されていない行は warning として報告するだけで、既定では失敗にしない.
+            max: KINDS
+                .iter()
+                .map(|kind| match kind {
+                    Kind::NotNormalized => (*kind, usize::MAX),
+                    _ => (*kind, 0),
+                })
+                .collect(),
+            records: 0,
+            counts: vec![0; KINDS.len()],
+            findings: Vec::new(),
+        };
+        for (name, n) in max {
+            let kind = Kind::from_name(name)?;
+            for m in ret.max.iter_mut().filter(|m| m.0 == kind) {
+                m.1 = *n;
+            }
+        }
+        Ok(ret)
+    }
+
+    pub fn add(&mut self, record: &[u8]) {
+        let mut kinds = check(record);
+        if !is_normalized_record(record, self.normalize) {
+            kinds.push(Kind::NotNormalized);
+        }
+        if !kinds.is_empty() {
+            for kind in &kinds {
+                self.counts[*kind as usize] += 1;
+            }
+            self.findings.push(Finding {
+                index: self.records,
+                kinds: kinds.iter().map(|kind| kind.name()).collect(),
+                record: display(record),
+            });
+        }
+        self.records += 1;
+    }
+
+    pub fn violations(&self) -> Vec<String> {
+        self.max
+            .iter()
+            .filter_map(|(kind, max)| {
+                let count = self.counts[*kind as usize];
+                if count > *max {
+                    Some(format!("{}: {} > {}", kind.name(), count, max))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn report(&self, format: &XQuoAuditFormat) -> Result<String> {
+        match format {
+            XQuoAuditFormat::Text => Ok(self.report_text()),
+            XQuoAuditFormat::Json => {
+                let report = Report {
+                    records: self.records,
+                    counts: KINDS
+                        .iter()
+                        .map(|kind| (kind.name(), self.counts[*kind as usize]))
+                        .collect(),
+                    findings: &self.findings,
+                    violations: self.violations(),
+                };
+                Ok(serde_json::to_string_pretty(&report)? + "\n")
+            }
+        }
+    }
+
+    fn report_text(&self) -> String {
+        let mut ret = String::new();
+        for finding in &self.findings {
+            ret.push_str(&format!(
+                "{}\t{}\t\"{}\"\n",
+                finding.index,
+                finding.kinds.join(","),
+                finding.record
+            ));
+        }
+        ret.push_str(&format!("records: {}\n", self.records));
+        for kind in KINDS {
+            ret.push_str(&format!(
+                "{}: {}\n",
+                kind.name(),
+                self.counts[*kind as usize]
+            ));
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audit::{check, display, Audit, Kind};
+    use crate::cli::XQuoNormalize;
+
+    #[test]
+    fn check_record() {
+        assert_eq!(check(b"test"), vec![]);
+        assert_eq!(check("テスト🦀".as_bytes()), vec![]);
+        assert_eq!(check(b"test\ntest"), vec![Kind::Newline]);
+        assert_eq!(check(b"test\ttest\x1b"), vec![Kind::Control]);
+        assert_eq!(check(b"-rf *"), vec![Kind::LeadingDash, Kind::Glob]);
+        assert_eq!(
+            check(b" test "),
+            vec![Kind::LeadingSpace, Kind::TrailingSpace]
+        );
+        assert_eq!(check(b"test[1]?"), vec![Kind::Glob]);
+        assert_eq!(check(b"test\xff"), vec![Kind::InvalidUtf8]);
+        assert_eq!(check("test\u{202e}txt.exe".as_bytes()), vec![Kind::Bidi]);
+    }
+
+    #[test]
+    fn check_confusable() {
+        // Cyrillic "а" mixed with Latin letters.
+        assert_eq!(check("pаypal".as_bytes()), vec![Kind::Confusable]);
+        assert_eq!(check("ｔｅｓｔ.txt".as_bytes()), vec![Kind::Confusable]);
+        assert_eq!(check("пароль".as_bytes()), vec![]);
+    }
+
+    #[test]
+    fn display_record() {
+        assert_eq!(display(b"test test"), "test test");
+        assert_eq!(display("テスト🦀".as_bytes()), "テスト🦀");
+        assert_eq!(display(b"a\nb\x1b\"\\"), "a\\nb\\u{1b}\\\"\\\\");
+        assert_eq!(display(b"a\xffb"), "a\\xffb");
+        assert_eq!(display(b"'a'"), "'a'");
+        assert_eq!(display("a\u{202e}b".as_bytes()), "a\\u{202e}b");
+    }
+
+    #[test]
+    fn violations() {
+        let mut audit = Audit::new(&[("glob".to_string(), 1)], &XQuoNormalize::None).unwrap();
+        audit.add(b"test");
+        audit.add(b"test*");
+        assert_eq!(audit.violations(), Vec::<String>::new());
+        audit.add(b"test?\n");
+        assert_eq!(
+            audit.violations(),
+            vec!["newline: 1 > 0".to_string(), "glob: 2 > 1".to_string()]
+        );
+        assert!(Audit::new(&[("unknown".to_string(), 1)], &XQuoNormalize::None).is_err());
+    }
+
+    #[test]
+    fn warn_not_normalized() {
+        let mut audit = Audit::new(&[], &XQuoNormalize::Nfc).unwrap();
+        audit.add("\u{30ac}".as_bytes());
+        audit.add("\u{30ab}\u{3099}".as_bytes());
+        assert_eq!(audit.findings.len(), 1);
+        assert_eq!(audit.findings[0].kinds, vec!["not-normalized"]);
+        assert_eq!(audit.violations(), Vec::<String>::new());
+
+        let mut audit =
+            Audit::new(&[("not-normalized".to_string(), 0)], &XQuoNormalize::Nfc).unwrap();
+        audit.add("\u{30ab}\u{3099}".as_bytes());
+        assert_eq!(
+            audit.violations(),
+            vec!["not-normalized: 1 > 0".to_string()]
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use std::io::{BufRead, BufReader};
+use std::ops::Range;
 
 pub struct BulkReader<T> {
     reader: BufReader<T>,
@@ -14,12 +15,12 @@ where
         let r = BufReader::<T>::new(reader);
         BulkReader { reader: r, size }
     }
-    pub fn read(&mut self, byte: u8) -> (Vec<Vec<u8>>, usize) {
+    pub fn read(&mut self, byte: u8) -> std::io::Result<(Vec<Vec<u8>>, usize)> {
         let mut bulk = Vec::<Vec<u8>>::new();
         let mut line_cnt = 0usize;
         loop {
             let mut buf = Vec::<u8>::new();
-            if self.reader.read_until(byte, &mut buf).unwrap() == 0 {
+            if self.reader.read_until(byte, &mut buf)? == 0 {
                 break;
             }
             bulk.push(buf); // push はどれくらいコストがかかる?
@@ -28,13 +29,32 @@ where
                 break;
             }
         }
-        (bulk, line_cnt)
+        Ok((bulk, line_cnt))
     }
 }
 
+// start から size byte 付近の区切り文字までを 1 つの chunk として切り出す.
+// chunk 内の各行の offset は worker 側で計算する.
+pub fn mapped_chunk(data: &[u8], start: usize, size: usize, byte: u8) -> Option<Range<usize>> {
+    let rest = &data[start..];
+    if rest.is_empty() {
+        return None;
+    }
+    let size = size.max(1);
+    let len = if rest.len() <= size {
+        rest.len()
+    } else {
+        match rest[size - 1..].iter().position(|b| *b == byte) {
+            Some(pos) => size + pos,
+            None => rest.len(),
+        }
+    };
+    Some(start..start + len)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::bulk::BulkReader;
+    use crate::bulk::{mapped_chunk, BulkReader};
 
     fn lines_to_bulk(src: &[&str], trim: bool) -> Vec<Vec<u8>> {
         let mut ret = Vec::<Vec<u8>>::new();
@@ -67,10 +87,10 @@ mod tests {
         let file = lines.as_bytes();
         let mut r = BulkReader::new(file, bulk_size);
 
-        let (b, s) = r.read(0);
+        let (b, s) = r.read(0).unwrap();
         assert_eq!(b, vec!["aa".as_bytes()]);
         assert_eq!(s, 1);
-        let (b, s) = r.read(0);
+        let (b, s) = r.read(0).unwrap();
         assert_eq!(b, Vec::<Vec<u8>>::new());
         assert_eq!(s, 0);
     }
@@ -85,7 +105,7 @@ mod tests {
         let file = lines.as_bytes();
         let mut r = BulkReader::new(file, bulk_size);
 
-        let (b, s) = r.read(0);
+        let (b, s) = r.read(0).unwrap();
         assert_eq!(b, Vec::<Vec<u8>>::new());
         assert_eq!(s, 0);
     }
@@ -100,10 +120,10 @@ mod tests {
         let file = lines.as_bytes();
         let mut r = BulkReader::new(file, bulk_size);
 
-        let (b, s) = r.read(0);
+        let (b, s) = r.read(0).unwrap();
         assert_eq!(b, lines_to_bulk(ex_lines, true));
         assert_eq!(s, 3);
-        let (b, s) = r.read(0);
+        let (b, s) = r.read(0).unwrap();
         assert_eq!(b, Vec::<Vec<u8>>::new());
         assert_eq!(s, 0);
     }
@@ -120,14 +140,52 @@ mod tests {
         let file = lines.as_bytes();
         let mut r = BulkReader::new(file, bulk_size);
 
-        let (b, s) = r.read(0);
+        let (b, s) = r.read(0).unwrap();
         assert_eq!(b, lines_to_bulk(&ex_lines[0..10], false));
         assert_eq!(s, 10);
-        let (b, s) = r.read(0);
+        let (b, s) = r.read(0).unwrap();
         assert_eq!(b, lines_to_bulk(&ex_lines[10..12], true));
         assert_eq!(s, 2);
-        let (b, s) = r.read(0);
+        let (b, s) = r.read(0).unwrap();
         assert_eq!(b, Vec::<Vec<u8>>::new());
         assert_eq!(s, 0);
     }
+
+    fn mapped_chunks(data: &[u8], size: usize) -> Vec<&[u8]> {
+        let mut ret = Vec::<&[u8]>::new();
+        let mut start = 0usize;
+        while let Some(range) = mapped_chunk(data, start, size, 0) {
+            start = range.end;
+            ret.push(&data[range]);
+        }
+        ret
+    }
+
+    #[test]
+    fn mapped_chunks_end_with_delimiter() {
+        let data = "aa\0bb\0cc\0dd".as_bytes();
+        let chunks = mapped_chunks(data, 4);
+        assert_eq!(chunks, vec!["aa\0bb\0".as_bytes(), "cc\0dd".as_bytes()]);
+
+        let chunks = mapped_chunks(data, 3);
+        assert_eq!(
+            chunks,
+            vec![
+                "aa\0".as_bytes(),
+                "bb\0".as_bytes(),
+                "cc\0".as_bytes(),
+                "dd".as_bytes()
+            ]
+        );
+    }
+
+    #[test]
+    fn mapped_chunks_with_long_line() {
+        let data = "aaaaaaaa\0b\0".as_bytes();
+        let chunks = mapped_chunks(data, 2);
+        assert_eq!(chunks, vec!["aaaaaaaa\0".as_bytes(), "b\0".as_bytes()]);
+
+        let chunks = mapped_chunks("".as_bytes(), 2);
+        assert_eq!(chunks, Vec::<&[u8]>::new());
+    }
 }
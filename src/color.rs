@@ -0,0 +1,180 @@
+use crate::cli::XQuoDialect;
+
+// quote のための仕組み、制御文字の escape、UTF-8 として不正な byte をそれぞれ色分けする.
+const QUOTE: &[u8] = b"\x1b[33m";
+const ESCAPE: &[u8] = b"\x1b[36m";
+const INVALID: &[u8] = b"\x1b[31m";
+const RESET: &[u8] = b"\x1b[0m";
+
+// quote された word を端末向けに色付けする.
+pub fn colorize(word: &[u8], dialect: &XQuoDialect) -> Vec<u8> {
+    let mut ret = Vec::<u8>::new();
+    let mut inside = false;
+    let mut i = 0;
+    while i < word.len() {
+        let rest = &word[i..];
+        let (n, color) = match dialect {
+            XQuoDialect::Bash => bash_segment(rest, &mut inside),
+            XQuoDialect::Fish => fish_segment(rest, &mut inside),
+        };
+        match color {
+            Some(color) => push_colored(&mut ret, &rest[..n], color),
+            None if inside => push_payload(&mut ret, &rest[..n]),
+            None => ret.extend_from_slice(&rest[..n]),
+        }
+        i += n;
+    }
+    ret
+}
+
+fn bash_segment(rest: &[u8], inside: &mut bool) -> (usize, Option<&'static [u8]>) {
+    if *inside && rest.starts_with(b"'\"'\"'") {
+        return (5, Some(QUOTE));
+    }
+    // `'$'\n''` のように quote を閉じて `$'...'` を挟み、また開いている部分.
+    let start = match (*inside, rest) {
+        (true, [b'\'', b'$', b'\'', ..]) => 1,
+        (false, [b'$', b'\'', ..]) => 0,
+        (true, [b'\'', ..]) => {
+            *inside = false;
+            return (1, None);
+        }
+        (false, [b'\'', ..]) => {
+            *inside = true;
+            return (1, None);
+        }
+        _ => return (payload_len(rest, *inside, b"'"), None),
+    };
+    let body = start + 2;
+    let end = match rest[body..].iter().position(|c| *c == b'\'') {
+        Some(pos) => body + pos + 1,
+        None => return (rest.len(), None),
+    };
+    // quote を閉じたまま終わる場合は、閉じ quote を payload 側に残す.
+    if start == 1 && rest.get(end) != Some(&b'\'') {
+        *inside = false;
+        return (1, None);
+    }
+    let color = escape_color(&rest[body..end]);
+    (if *inside { end + 1 } else { end }, Some(color))
+}
+
+fn fish_segment(rest: &[u8], inside: &mut bool) -> (usize, Option<&'static [u8]>) {
+    match (*inside, rest) {
+        (true, [b'\\', b'\'' | b'\\', ..]) => (2, Some(QUOTE)),
+        // `'\n'` のように quote を閉じて escape を挟み、また開いている部分.
+        (true, [b'\'', b'\\', ..]) | (false, [b'\\', ..]) => {
+            let start = if *inside { 1 } else { 0 };
+            let mut end = start;
+            while rest.get(end) == Some(&b'\\') {
+                end += escape_len(&rest[end..]);
+            }
+            if start == 1 && rest.get(end) != Some(&b'\'') {
+                *inside = false;
+                return (1, None);
+            }
+            let color = escape_color(&rest[start..end]);
+            (if *inside { end + 1 } else { end }, Some(color))
+        }
+        (_, [b'\'', ..]) => {
+            *inside = !*inside;
+            (1, None)
+        }
+        _ => (payload_len(rest, *inside, b"'\\"), None),
+    }
+}
+
+// `\x` の後には 16 進数が 2 桁続き、それ以外は 1 文字で終わる.
+fn escape_len(rest: &[u8]) -> usize {
+    match rest.get(1) {
+        Some(b'x') => {
+            2 + rest[2..]
+                .iter()
+                .take(2)
+                .filter(|c| c.is_ascii_hexdigit())
+                .count()
+        }
+        Some(_) => 2,
+        None => 1,
+    }
+}
+
+// `\x80` 以上は UTF-8 として不正な byte を escape したもの.
+fn escape_color(escape: &[u8]) -> &'static [u8] {
+    let pos = escape.windows(2).position(|v| v == b"\\x");
+    match pos.and_then(|pos| escape.get(pos + 2)) {
+        Some(b'8'..=b'9' | b'a'..=b'f') => INVALID,
+        _ => ESCAPE,
+    }
+}
+
+fn payload_len(rest: &[u8], inside: bool, stops: &[u8]) -> usize {
+    let stops: &[u8] = if inside { stops } else { b"'$\\" };
+    rest.iter()
+        .position(|c| stops.contains(c))
+        .unwrap_or(rest.len())
+        .max(1)
+}
+
+// `--no-escape` では不正な byte がそのまま quote の中に入るので、その部分も色付けする.
+fn push_payload(ret: &mut Vec<u8>, payload: &[u8]) {
+    for chunk in payload.utf8_chunks() {
+        ret.extend_from_slice(chunk.valid().as_bytes());
+        if !chunk.invalid().is_empty() {
+            push_colored(ret, chunk.invalid(), INVALID);
+        }
+    }
+}
+
+fn push_colored(ret: &mut Vec<u8>, segment: &[u8], color: &[u8]) {
+    ret.extend_from_slice(color);
+    ret.extend_from_slice(segment);
+    ret.extend_from_slice(RESET);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::XQuoDialect;
+    use crate::color::colorize;
+
+    fn plain(word: &[u8], dialect: XQuoDialect) -> String {
+        String::from_utf8_lossy(&colorize(word, &dialect))
+            .replace("\x1b[33m", "<q>")
+            .replace("\x1b[36m", "<e>")
+            .replace("\x1b[31m", "<i>")
+            .replace("\x1b[0m", "</>")
+    }
+
+    #[test]
+    fn colorize_bash() {
+        let bash = XQuoDialect::Bash;
+        assert_eq!(plain(b"'test'", bash), "'test'");
+        assert_eq!(plain(b"'test'\"'\"'test'", bash), "'test<q>'\"'\"'</>test'");
+        assert_eq!(
+            plain(b"'test'$'\\n''test'$'\\n'''", bash),
+            "'test<e>'$'\\n''</>test<e>'$'\\n''</>'"
+        );
+        assert_eq!(
+            plain(b"$'\\xe3\\x83''test'$'\\xff'", bash),
+            "<i>$'\\xe3\\x83'</>'test'<i>$'\\xff'</>"
+        );
+        assert_eq!(
+            plain(b"'test''\xff''test'", bash),
+            "'test''<i>\u{fffd}</>''test'"
+        );
+    }
+
+    #[test]
+    fn colorize_fish() {
+        let fish = XQuoDialect::Fish;
+        assert_eq!(
+            plain(b"'test\\'test\\\\'", fish),
+            "'test<q>\\'</>test<q>\\\\</>'"
+        );
+        assert_eq!(
+            plain(b"'test'\\n'test'\\x1b''", fish),
+            "'test<e>'\\n'</>test<e>'\\x1b'</>'"
+        );
+        assert_eq!(plain(b"\\xff'test'", fish), "<i>\\xff</>'test'");
+    }
+}
@@ -0,0 +1,486 @@
+use anyhow::{anyhow, Context, Result};
+use clap::builder::BoolishValueParser;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+const ENV_PREFIX: &str = "XQUO_";
+const PROFILE: &str = "profile";
+const PROFILES: &str = "profiles";
+
+pub enum Source {
+    Config(PathBuf),
+    Profile(String, PathBuf),
+    Env(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Config(path) => write!(f, "config {}", path.display()),
+            Source::Profile(name, path) => write!(f, "profile {} in {}", name, path.display()),
+            Source::Env(name) => write!(f, "env {}", name),
+        }
+    }
+}
+
+// コマンドライン以外から与えられたオプションの値.
+pub struct Setting {
+    key: String,
+    value: String,
+    source: Source,
+}
+
+pub struct Config {
+    path: PathBuf,
+    table: toml::Table,
+}
+
+impl Config {
+    // $XDG_CONFIG_HOME/xquo/config.toml を読み込む. ファイルがなければ None を返す.
+    pub fn load(env: &dyn Fn(&str) -> Option<String>) -> Result<Option<Config>> {
+        let dir = match env("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => match env("HOME").filter(|v| !v.is_empty()) {
+                Some(home) => Path::new(&home).join(".config"),
+                None => return Ok(None),
+            },
+        };
+        let path = dir.join("xquo").join("config.toml");
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+        Config::parse(path, &text).map(Some)
+    }
+
+    pub fn parse(path: PathBuf, text: &str) -> Result<Config> {
+        let table = text
+            .parse::<toml::Table>()
+            .with_context(|| format!("could not parse {}", path.display()))?;
+        Ok(Config { path, table })
+    }
+
+    fn profile(&self, name: &str) -> Result<&toml::Table> {
+        self.table
+            .get(PROFILES)
+            .and_then(|v| v.as_table())
+            .and_then(|v| v.get(name))
+            .and_then(|v| v.as_table())
+            .ok_or_else(|| anyhow!("profile {} is not defined in {}", name, self.path.display()))
+    }
+}
+
+// 設定できるオプションの long name. 位置引数や help などは対象外.
+fn keys(cmd: &Command) -> Vec<String> {
+    cmd.get_arguments()
+        .filter(|arg| !matches!(arg.get_action(), ArgAction::Help | ArgAction::Version))
+        .filter_map(|arg| arg.get_long())
+        .filter(|long| *long != PROFILE)
+        .map(|long| long.to_string())
+        .collect()
+}
+
+fn env_name(key: &str) -> String {
+    ENV_PREFIX.to_string() + &key.to_ascii_uppercase().replace('-', "_")
+}
+
+fn toml_to_string(table: &toml::Table, key: &str, path: &Path) -> Result<Option<String>> {
+    let value = match table.get(key).or_else(|| table.get(&key.replace('-', "_"))) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    match value {
+        toml::Value::String(v) => Ok(Some(v.clone())),
+        toml::Value::Integer(v) => Ok(Some(v.to_string())),
+        toml::Value::Boolean(v) => Ok(Some(v.to_string())),
+        _ => Err(anyhow!("{}: invalid value for {}", path.display(), key)),
+    }
+}
+
+fn check_keys(table: &toml::Table, keys: &[String], path: &Path, allowed: &[&str]) -> Result<()> {
+    for key in table.keys() {
+        let known = keys
+            .iter()
+            .any(|k| *k == *key || k.replace('-', "_") == *key);
+        if !known && !allowed.contains(&key.as_str()) {
+            return Err(anyhow!("{}: unknown option {}", path.display(), key));
+        }
+    }
+    Ok(())
+}
+
+fn parse_bool(setting: &Setting) -> Result<bool> {
+    match setting.value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "" | "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(anyhow!(
+            "invalid value {:?} for {} from {}",
+            setting.value,
+            setting.key,
+            setting.source
+        )),
+    }
+}
+
+// 環境変数、profile、設定ファイルの順番で、各オプションの値を探す.
+fn settings(
+    cmd: &Command,
+    config: Option<&Config>,
+    profile: Option<&str>,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<Vec<Setting>> {
+    let keys = keys(cmd);
+    let profile = match (config, profile) {
+        (Some(config), Some(name)) => {
+            let table = config.profile(name)?;
+            check_keys(table, &keys, &config.path, &[])?;
+            Some((name, table))
+        }
+        (None, Some(name)) => return Err(anyhow!("profile {} is not defined", name)),
+        _ => None,
+    };
+    if let Some(config) = config {
+        check_keys(&config.table, &keys, &config.path, &[PROFILE, PROFILES])?;
+    }
+
+    let mut ret = Vec::<Setting>::new();
+    for key in keys {
+        let name = env_name(&key);
+        if let Some(value) = env(&name) {
+            ret.push(Setting {
+                key,
+                value,
+                source: Source::Env(name),
+            });
+            continue;
+        }
+        if let (Some(config), Some((profile, table))) = (config, profile) {
+            if let Some(value) = toml_to_string(table, &key, &config.path)? {
+                ret.push(Setting {
+                    key,
+                    value,
+                    source: Source::Profile(profile.to_string(), config.path.clone()),
+                });
+                continue;
+            }
+        }
+        if let Some(config) = config {
+            if let Some(value) = toml_to_string(&config.table, &key, &config.path)? {
+                ret.push(Setting {
+                    key,
+                    value,
+                    source: Source::Config(config.path.clone()),
+                });
+            }
+        }
+    }
+    Ok(ret)
+}
+
+fn from_command_line(matches: &ArgMatches, cmd: &Command, key: &str) -> bool {
+    cmd.get_arguments()
+        .find(|arg| arg.get_long() == Some(key))
+        .is_some_and(|arg| is_from_command_line(matches, arg))
+}
+
+fn is_from_command_line(matches: &ArgMatches, arg: &Arg) -> bool {
+    matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+}
+
+// コマンドラインで指定されたオプションと衝突する設定は使わない.
+fn conflicts_with_command_line(matches: &ArgMatches, cmd: &Command, key: &str) -> bool {
+    let arg = match cmd.get_arguments().find(|arg| arg.get_long() == Some(key)) {
+        Some(arg) => arg,
+        None => return false,
+    };
+    let conflicts = |a: &Arg, b: &Arg| {
+        cmd.get_arg_conflicts_with(a)
+            .iter()
+            .any(|v| v.get_id() == b.get_id())
+    };
+    cmd.get_arguments()
+        .filter(|other| is_from_command_line(matches, other))
+        .any(|other| conflicts(arg, other) || conflicts(other, arg))
+}
+
+// SetTrue で指定する long オプションの id.
+fn flags(cmd: &Command) -> Vec<String> {
+    cmd.get_arguments()
+        .filter(|arg| matches!(arg.get_action(), ArgAction::SetTrue))
+        .filter(|arg| arg.get_long().is_some())
+        .map(|arg| arg.get_id().to_string())
+        .collect()
+}
+
+// flag は `--no-escape=false` のように値も受け付け、設定された値を打ち消せるようにする.
+pub fn command(cmd: Command) -> Command {
+    let flags = flags(&cmd);
+    with_bool_values(cmd, &flags)
+}
+
+fn with_bool_values(cmd: Command, flags: &[String]) -> Command {
+    flags.iter().fold(cmd, |cmd, id| {
+        cmd.mut_arg(id, |arg| {
+            arg.action(ArgAction::Set)
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_value("false")
+                .default_missing_value("true")
+                .value_parser(BoolishValueParser::new())
+        })
+    })
+}
+
+// 設定された値を、そのオプションの value parser だけで確認する.
+fn check_value(arg: &Arg, setting: &Setting) -> Result<()> {
+    let value = Arg::new("value")
+        .allow_hyphen_values(true)
+        .value_parser(arg.get_value_parser().clone());
+    Command::new("xquo")
+        .no_binary_name(true)
+        .arg(value)
+        .try_get_matches_from([&setting.value])
+        .map(|_| ())
+        .map_err(|_| {
+            anyhow!(
+                "invalid value {:?} for {} from {}",
+                setting.value,
+                setting.key,
+                setting.source
+            )
+        })
+}
+
+// コマンドラインで指定されていないオプションの既定値を設定から補って parse する.
+// 既定値にすることで、設定した値がコマンドラインのオプションと衝突しないようにする.
+pub fn get_matches(
+    cmd: Command,
+    args: Vec<OsString>,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<(ArgMatches, Vec<Setting>, Option<PathBuf>)> {
+    let flags = flags(&cmd);
+    let cmd = with_bool_values(cmd, &flags);
+    let matches = cmd.clone().get_matches_from(&args);
+    let config = Config::load(env)?;
+    let profile = match matches.get_one::<String>(PROFILE) {
+        Some(profile) => Some(profile.clone()),
+        None => match env(&env_name(PROFILE)) {
+            Some(profile) => Some(profile),
+            None => match &config {
+                Some(config) => toml_to_string(&config.table, PROFILE, &config.path)?,
+                None => None,
+            },
+        },
+    };
+    let settings: Vec<Setting> = settings(&cmd, config.as_ref(), profile.as_deref(), env)?
+        .into_iter()
+        .filter(|setting| !from_command_line(&matches, &cmd, &setting.key))
+        .filter(|setting| !conflicts_with_command_line(&matches, &cmd, &setting.key))
+        .collect();
+
+    let mut defaults = Vec::<(String, String)>::new();
+    if let (Some(profile), None) = (&profile, matches.get_one::<String>(PROFILE)) {
+        defaults.push((PROFILE.to_string(), profile.clone()));
+    }
+    for setting in &settings {
+        let arg = cmd
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(setting.key.as_str()))
+            .expect("key is taken from the arguments");
+        // 不正な値はどこで設定されたかを示して error にする.
+        let value = if flags.iter().any(|id| id == arg.get_id()) {
+            parse_bool(setting)?.to_string()
+        } else {
+            check_value(arg, setting)?;
+            setting.value.clone()
+        };
+        defaults.push((arg.get_id().to_string(), value));
+    }
+    if defaults.is_empty() {
+        return Ok((matches, settings, config.map(|v| v.path)));
+    }
+    let cmd = defaults.into_iter().fold(cmd, |cmd, (id, value)| {
+        cmd.mut_arg(id, |arg| arg.default_value(value))
+    });
+    let matches = cmd.get_matches_from(args);
+    Ok((matches, settings, config.map(|v| v.path)))
+}
+
+// 有効な設定値とその出所を表示する.
+pub fn show(
+    cmd: &Command,
+    matches: &ArgMatches,
+    settings: &[Setting],
+    path: Option<&Path>,
+) -> String {
+    let mut ret = match path {
+        Some(path) => format!("# config file: {}\n", path.display()),
+        None => "# config file: none\n".to_string(),
+    };
+    if let Some(profile) = matches.get_one::<String>(PROFILE) {
+        ret.push_str(&format!("# profile: {}\n", profile));
+    }
+    for arg in cmd.get_arguments() {
+        let key = match arg.get_long() {
+            Some(key) if keys(cmd).iter().any(|k| k == key) => key,
+            _ => continue,
+        };
+        let id = arg.get_id().as_str();
+        let source = match settings.iter().find(|setting| setting.key == key) {
+            Some(setting) => setting.source.to_string(),
+            None => match matches.value_source(id) {
+                Some(ValueSource::CommandLine) => "command line".to_string(),
+                Some(_) => "default".to_string(),
+                None => {
+                    ret.push_str(&format!("# {} is not set\n", key));
+                    continue;
+                }
+            },
+        };
+        let value: Vec<String> = matches
+            .get_raw(id)
+            .into_iter()
+            .flatten()
+            .map(|v| toml_value(&v.to_string_lossy()))
+            .collect();
+        ret.push_str(&format!("{} = {} # {}\n", key, value.join(", "), source));
+    }
+    ret
+}
+
+fn toml_value(value: &str) -> String {
+    if value == "true" || value == "false" || value.parse::<i64>().is_ok() {
+        value.to_string()
+    } else {
+        toml::Value::String(value.to_string()).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{command as with_flag_values, conflicts_with_command_line};
+    use crate::config::{env_name, settings, show, Config};
+    use clap::{Arg, ArgAction, Command};
+    use std::path::PathBuf;
+
+    fn command() -> Command {
+        Command::new("xquo")
+            .arg(
+                Arg::new("out_delimiter")
+                    .short('o')
+                    .long("out-delimiter")
+                    .default_value("lf"),
+            )
+            .arg(
+                Arg::new("workers")
+                    .short('w')
+                    .long("workers")
+                    .default_value("1"),
+            )
+            .arg(
+                Arg::new("no_escape")
+                    .short('n')
+                    .long("no-escape")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("input_encoding").long("input-encoding"))
+            .arg(Arg::new("profile").long("profile"))
+    }
+
+    fn config(text: &str) -> Config {
+        Config::parse(PathBuf::from("config.toml"), text).unwrap()
+    }
+
+    #[test]
+    fn env_names() {
+        assert_eq!(env_name("out-delimiter"), "XQUO_OUT_DELIMITER");
+        assert_eq!(env_name("workers"), "XQUO_WORKERS");
+    }
+
+    #[test]
+    fn settings_in_order_of_env_profile_config() {
+        let config = config(
+            r#"
+out-delimiter = "null"
+workers = 4
+no_escape = true
+
+[profiles.sql]
+workers = 2
+"#,
+        );
+        let env = |name: &str| (name == "XQUO_OUT_DELIMITER").then(|| "lf".to_string());
+        let found = settings(&command(), Some(&config), Some("sql"), &env).unwrap();
+        let found: Vec<(String, String, String)> = found
+            .into_iter()
+            .map(|v| (v.key, v.value, v.source.to_string()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "out-delimiter".into(),
+                    "lf".into(),
+                    "env XQUO_OUT_DELIMITER".into()
+                ),
+                (
+                    "workers".into(),
+                    "2".into(),
+                    "profile sql in config.toml".into()
+                ),
+                (
+                    "no-escape".into(),
+                    "true".into(),
+                    "config config.toml".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn fail_on_unknown_option_or_profile() {
+        let none = |_: &str| None;
+        assert!(settings(&command(), Some(&config("unknown = 1")), None, &none).is_err());
+        assert!(settings(&command(), Some(&config("workers = 1.5")), None, &none).is_err());
+        assert!(settings(&command(), Some(&config("")), Some("sql"), &none).is_err());
+    }
+
+    #[test]
+    fn turn_off_flags() {
+        let matches = with_flag_values(command()).get_matches_from(["xquo", "--no-escape=false"]);
+        assert_eq!(matches.get_one::<bool>("no_escape"), Some(&false));
+        let matches = with_flag_values(command()).get_matches_from(["xquo", "-n"]);
+        assert_eq!(matches.get_one::<bool>("no_escape"), Some(&true));
+    }
+
+    #[test]
+    fn skip_settings_conflicting_with_command_line() {
+        let cmd = command()
+            .arg(Arg::new("format").long("format"))
+            .arg(Arg::new("assign").long("assign").conflicts_with("format"));
+        let matches = cmd.clone().get_matches_from(["xquo", "--assign", "X"]);
+        assert!(conflicts_with_command_line(&matches, &cmd, "format"));
+        assert!(!conflicts_with_command_line(&matches, &cmd, "workers"));
+    }
+
+    #[test]
+    fn show_settings() {
+        let config = config("workers = 4\n");
+        let none = |_: &str| None;
+        let found = settings(&command(), Some(&config), None, &none).unwrap();
+        let matches = command().get_matches_from(["xquo", "--workers=4", "-n"]);
+        assert_eq!(
+            show(&command(), &matches, &found, None),
+            "# config file: none
+out-delimiter = \"lf\" # default
+workers = 4 # config config.toml
+no-escape = true # command line
+# input-encoding is not set
+"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::{anyhow, Result};
+
+use crate::cli::{XQuoDialect, XQuoNormalize};
+use crate::normalize::normalize;
+use crate::quote::DoQuote;
+use crate::split::{split, Token};
+
+// bash と fish のどちらでも同じ意味になる operator. fd の番号が前に付いてもよい.
+const PORTABLE_OPERATORS: &[&[u8]] = &[
+    b"|", b"&&", b"||", b";", b"&", b"<", b">", b">>", b"&>", b">&", b"<&",
+];
+
+// from の規則でコマンドラインを word に分け、form で正規化して q で quote し直す.
+// 実行時にしか値が決まらない展開や、もう一方の shell にない operator は error にする.
+pub fn convert(
+    line: &[u8],
+    from: &XQuoDialect,
+    q: &dyn DoQuote,
+    form: &XQuoNormalize,
+) -> Result<Vec<u8>> {
+    let mut ret = Vec::<u8>::new();
+    let mut dup = false;
+    for token in split(line, from)? {
+        if !ret.is_empty() && !dup {
+            ret.push(b' ');
+        }
+        match token {
+            Token::Word(word) => {
+                // `2>&1` の fd の番号は quote しない.
+                if dup && (word.iter().all(u8::is_ascii_digit) || word == b"-") {
+                    ret.extend_from_slice(&word);
+                } else {
+                    ret.extend_from_slice(&q.quote_bytes(&normalize(&word, form)));
+                }
+                dup = false;
+            }
+            Token::Operator(op) => {
+                let fd = op.iter().take_while(|c| c.is_ascii_digit()).count();
+                let name = &op[fd..];
+                if !PORTABLE_OPERATORS.contains(&name) {
+                    return Err(anyhow!(
+                        "operator {:?} cannot be translated",
+                        String::from_utf8_lossy(&op)
+                    ));
+                }
+                ret.extend_from_slice(&op);
+                dup = name == b">&" || name == b"<&";
+            }
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::{XQuoDialect, XQuoNormalize};
+    use crate::convert::convert;
+    use crate::quote::{QuoteFish, QuotePrintable};
+
+    #[test]
+    fn convert_bash_to_fish() {
+        let fish = QuoteFish { printable: true };
+        assert_eq!(
+            convert(
+                b"grep -r 'it'\"'\"'s' $'a\\tb' 2>&1 | less",
+                &XQuoDialect::Bash,
+                &fish,
+                &XQuoNormalize::None
+            )
+            .unwrap(),
+            b"'grep' '-r' 'it\\'s' 'a'\\t'b' 2>&1 | 'less'"
+        );
+        assert!(convert(
+            b"cat <<< x",
+            &XQuoDialect::Bash,
+            &fish,
+            &XQuoNormalize::None
+        )
+        .is_err());
+        assert!(convert(
+            b"echo $HOME",
+            &XQuoDialect::Bash,
+            &fish,
+            &XQuoNormalize::None
+        )
+        .is_err());
+        assert!(convert(
+            b"echo {a,b}",
+            &XQuoDialect::Bash,
+            &fish,
+            &XQuoNormalize::None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn convert_fish_to_bash() {
+        let bash = QuotePrintable {};
+        assert_eq!(
+            convert(
+                b"echo 'it\\'s' a\\ b",
+                &XQuoDialect::Fish,
+                &bash,
+                &XQuoNormalize::None
+            )
+            .unwrap(),
+            b"'echo' 'it'\"'\"'s' 'a b'"
+        );
+        assert!(convert(
+            b"echo (ls)",
+            &XQuoDialect::Fish,
+            &bash,
+            &XQuoNormalize::None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn normalize_each_word() {
+        // 全角の quote は正規化すると `'` になるが、quote としては解釈しない.
+        let bash = QuotePrintable {};
+        assert_eq!(
+            convert(
+                "echo ＇a b＇".as_bytes(),
+                &XQuoDialect::Bash,
+                &bash,
+                &XQuoNormalize::Nfkc
+            )
+            .unwrap(),
+            b"'echo' ''\"'\"'a' 'b'\"'\"''"
+        );
+    }
+}
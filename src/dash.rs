@@ -0,0 +1,63 @@
+use anyhow::{anyhow, Result};
+use std::borrow::Cow;
+
+use crate::cli::XQuoLeadingDash;
+
+// `-` で始まる行がコマンドのオプションとして解釈されないようにする.
+pub fn protect_leading_dash<'a>(
+    record: &'a [u8],
+    policy: &XQuoLeadingDash,
+) -> Result<Cow<'a, [u8]>> {
+    if !record.starts_with(b"-") {
+        return Ok(Cow::Borrowed(record));
+    }
+    match policy {
+        XQuoLeadingDash::PrefixDotSlash => Ok(Cow::Owned([b"./", record].concat())),
+        XQuoLeadingDash::Error => Err(anyhow!(
+            "{:?} begins with a dash",
+            String::from_utf8_lossy(record)
+        )),
+        XQuoLeadingDash::Warn => {
+            eprintln!(
+                "xquo: warning: {:?} begins with a dash",
+                String::from_utf8_lossy(record)
+            );
+            Ok(Cow::Borrowed(record))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::XQuoLeadingDash;
+    use crate::dash::protect_leading_dash;
+
+    #[test]
+    fn prefix_dot_slash() {
+        let policy = XQuoLeadingDash::PrefixDotSlash;
+        assert_eq!(
+            protect_leading_dash(b"-rf", &policy).unwrap().as_ref(),
+            b"./-rf"
+        );
+        assert_eq!(
+            protect_leading_dash(b"test-rf", &policy).unwrap().as_ref(),
+            b"test-rf"
+        );
+        assert_eq!(
+            protect_leading_dash(b"/-rf", &policy).unwrap().as_ref(),
+            b"/-rf"
+        );
+    }
+
+    #[test]
+    fn error_and_warn() {
+        assert!(protect_leading_dash(b"-rf", &XQuoLeadingDash::Error).is_err());
+        assert!(protect_leading_dash(b"test", &XQuoLeadingDash::Error).is_ok());
+        assert_eq!(
+            protect_leading_dash(b"-rf", &XQuoLeadingDash::Warn)
+                .unwrap()
+                .as_ref(),
+            b"-rf"
+        );
+    }
+}
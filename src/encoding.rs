@@ -0,0 +1,254 @@
+use anyhow::{anyhow, Result};
+use encoding_rs::{DecoderResult, EncoderResult, Encoding, UTF_8};
+use std::borrow::Cow;
+
+use crate::cli::XQuoUnmappable;
+
+// encoding_rs は latin1 を windows-1252 として扱うので、ISO-8859-1 は別に扱う.
+const LATIN1_LABELS: &[&str] = &["latin1", "l1", "iso-8859-1", "iso8859-1", "iso_8859-1"];
+
+#[derive(Clone, Copy, Debug)]
+pub enum Codec {
+    Latin1,
+    Other(&'static Encoding),
+}
+
+impl Codec {
+    pub fn for_label(label: &str) -> Result<Codec> {
+        if LATIN1_LABELS.contains(&label.trim().to_ascii_lowercase().as_str()) {
+            return Ok(Codec::Latin1);
+        }
+        match Encoding::for_label(label.as_bytes()) {
+            Some(encoding) => Ok(Codec::Other(encoding.output_encoding())),
+            None => Err(anyhow!("unknown encoding: {}", label)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::Latin1 => "ISO-8859-1",
+            Codec::Other(encoding) => encoding.name(),
+        }
+    }
+
+    fn is_ascii_compatible(&self) -> bool {
+        match self {
+            Codec::Latin1 => true,
+            Codec::Other(encoding) => encoding.is_ascii_compatible(),
+        }
+    }
+}
+
+// 行を UTF-8 に変換する. escape では変換できない byte をそのまま残し、quote するときに escape させる.
+pub fn decode<'a>(
+    record: &'a [u8],
+    codec: &Codec,
+    unmappable: &XQuoUnmappable,
+) -> Result<Cow<'a, [u8]>> {
+    if codec.is_ascii_compatible() && record.is_ascii() {
+        return Ok(Cow::Borrowed(record));
+    }
+    let encoding = match codec {
+        Codec::Latin1 => {
+            let text: String = record.iter().map(|b| *b as char).collect();
+            return Ok(Cow::Owned(text.into_bytes()));
+        }
+        Codec::Other(encoding) => *encoding,
+    };
+    if encoding == UTF_8 && std::str::from_utf8(record).is_ok() {
+        return Ok(Cow::Borrowed(record));
+    }
+
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let mut ret = Vec::<u8>::new();
+    let mut buf = String::new();
+    let mut pos = 0;
+    loop {
+        let rest = &record[pos..];
+        buf.reserve(
+            decoder
+                .max_utf8_buffer_length_without_replacement(rest.len())
+                .unwrap_or(rest.len() * 3),
+        );
+        let (result, read) = decoder.decode_to_string_without_replacement(rest, &mut buf, true);
+        pos += read;
+        ret.extend_from_slice(buf.as_bytes());
+        buf.clear();
+        match result {
+            DecoderResult::InputEmpty => break,
+            DecoderResult::OutputFull => {}
+            DecoderResult::Malformed(bad, extra) => {
+                let start = pos.saturating_sub(bad as usize + extra as usize);
+                match unmappable {
+                    XQuoUnmappable::Fail => {
+                        return Err(anyhow!(
+                            "could not decode line as {}: invalid byte sequence at byte {}",
+                            codec.name(),
+                            start
+                        ))
+                    }
+                    XQuoUnmappable::Replace => ret.extend_from_slice("\u{fffd}".as_bytes()),
+                    XQuoUnmappable::Escape => ret.extend_from_slice(&record[start..pos]),
+                }
+            }
+        }
+    }
+    Ok(Cow::Owned(ret))
+}
+
+// quote した出力を指定された encoding に変換する.
+// 出力では全ての文字が single quote の中にあるので、escape では `'$'\xNN''` に置き換える.
+pub fn encode(out: Vec<u8>, codec: &Codec, unmappable: &XQuoUnmappable) -> Result<Vec<u8>> {
+    if codec.is_ascii_compatible() && out.is_ascii() {
+        return Ok(out);
+    }
+    if let Codec::Other(encoding) = codec {
+        if *encoding == UTF_8 {
+            return Ok(out);
+        }
+    }
+    let mut ret = Vec::<u8>::new();
+    // QuoteBasic は UTF-8 として不正な byte 列をそのまま出力するので、変換せずに残す.
+    for chunk in out.utf8_chunks() {
+        encode_str(chunk.valid(), codec, unmappable, &mut ret)?;
+        ret.extend_from_slice(chunk.invalid());
+    }
+    Ok(ret)
+}
+
+fn encode_str(
+    text: &str,
+    codec: &Codec,
+    unmappable: &XQuoUnmappable,
+    ret: &mut Vec<u8>,
+) -> Result<()> {
+    let encoding = match codec {
+        Codec::Latin1 => {
+            for c in text.chars() {
+                match u8::try_from(c) {
+                    Ok(b) => ret.push(b),
+                    Err(_) => push_unmappable(c, codec, unmappable, ret)?,
+                }
+            }
+            return Ok(());
+        }
+        Codec::Other(encoding) => *encoding,
+    };
+    let mut encoder = encoding.new_encoder();
+    let mut pos = 0;
+    loop {
+        let rest = &text[pos..];
+        ret.reserve(
+            encoder
+                .max_buffer_length_from_utf8_without_replacement(rest.len())
+                .unwrap_or(rest.len() * 4),
+        );
+        let (result, read) = encoder.encode_from_utf8_to_vec_without_replacement(rest, ret, true);
+        pos += read;
+        match result {
+            EncoderResult::InputEmpty => return Ok(()),
+            EncoderResult::OutputFull => {}
+            EncoderResult::Unmappable(c) => push_unmappable(c, codec, unmappable, ret)?,
+        }
+    }
+}
+
+fn push_unmappable(
+    c: char,
+    codec: &Codec,
+    unmappable: &XQuoUnmappable,
+    ret: &mut Vec<u8>,
+) -> Result<()> {
+    match unmappable {
+        XQuoUnmappable::Fail => Err(anyhow!("could not encode {:?} as {}", c, codec.name())),
+        XQuoUnmappable::Replace => {
+            ret.push(b'?');
+            Ok(())
+        }
+        XQuoUnmappable::Escape => {
+            let mut buf = [0; 4];
+            let escaped: String = c
+                .encode_utf8(&mut buf)
+                .bytes()
+                .map(|b| format!("\\x{:02x}", b))
+                .collect();
+            ret.extend_from_slice(format!("'$'{}''", escaped).as_bytes());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::XQuoUnmappable;
+    use crate::encoding::{decode, encode, Codec};
+
+    // "テスト" の Shift_JIS と EUC-JP.
+    const SJIS: &[u8] = b"\x83\x65\x83\x58\x83\x67";
+    const EUCJP: &[u8] = b"\xa5\xc6\xa5\xb9\xa5\xc8";
+
+    #[test]
+    fn decode_record() {
+        let sjis = Codec::for_label("shift_jis").unwrap();
+        let eucjp = Codec::for_label("euc-jp").unwrap();
+        let latin1 = Codec::for_label("latin1").unwrap();
+        let fail = XQuoUnmappable::Fail;
+        assert_eq!(
+            decode(SJIS, &sjis, &fail).unwrap().as_ref(),
+            "テスト".as_bytes()
+        );
+        assert_eq!(
+            decode(EUCJP, &eucjp, &fail).unwrap().as_ref(),
+            "テスト".as_bytes()
+        );
+        assert_eq!(decode(b"test", &sjis, &fail).unwrap().as_ref(), b"test");
+        assert_eq!(
+            decode(b"\xe9\x80", &latin1, &fail).unwrap().as_ref(),
+            "é\u{80}".as_bytes()
+        );
+        assert!(Codec::for_label("unknown").is_err());
+    }
+
+    #[test]
+    fn decode_malformed() {
+        let sjis = Codec::for_label("shift_jis").unwrap();
+        let record = b"\x83\x65\xa0";
+        assert!(decode(record, &sjis, &XQuoUnmappable::Fail).is_err());
+        assert_eq!(
+            decode(record, &sjis, &XQuoUnmappable::Replace)
+                .unwrap()
+                .as_ref(),
+            "テ\u{fffd}".as_bytes()
+        );
+        assert_eq!(
+            decode(record, &sjis, &XQuoUnmappable::Escape)
+                .unwrap()
+                .as_ref(),
+            ["テ".as_bytes(), b"\xa0"].concat()
+        );
+    }
+
+    #[test]
+    fn encode_output() {
+        let sjis = Codec::for_label("sjis").unwrap();
+        let latin1 = Codec::for_label("iso-8859-1").unwrap();
+        let fail = XQuoUnmappable::Fail;
+        assert_eq!(
+            encode("'テスト'\n".as_bytes().to_vec(), &sjis, &fail).unwrap(),
+            [b"'", SJIS, b"'\n"].concat()
+        );
+        assert_eq!(
+            encode("'é'".as_bytes().to_vec(), &latin1, &fail).unwrap(),
+            b"'\xe9'"
+        );
+        assert!(encode("'🦀'".as_bytes().to_vec(), &sjis, &fail).is_err());
+        assert_eq!(
+            encode("'a🦀'".as_bytes().to_vec(), &sjis, &XQuoUnmappable::Replace).unwrap(),
+            b"'a?'"
+        );
+        assert_eq!(
+            encode("'a🦀'".as_bytes().to_vec(), &sjis, &XQuoUnmappable::Escape).unwrap(),
+            b"'a'$'\\xf0\\x9f\\xa6\\x80'''"
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::{anyhow, Result};
+
+use crate::cli::{XQuoAssign, XQuoAssignPrefix, XQuoDialect, XQuoEnvStyle};
+
+// `NAME=value` を名前と値に分ける. 名前は shell の変数名として正しくなければならない.
+pub fn split_assignment(record: &[u8]) -> Result<(&[u8], &[u8])> {
+    let pos = record.iter().position(|c| *c == b'=').ok_or_else(|| {
+        anyhow!(
+            "{:?} is not a NAME=value pair",
+            String::from_utf8_lossy(record)
+        )
+    })?;
+    let (name, value) = (&record[..pos], &record[pos + 1..]);
+    if !is_name(name) {
+        return Err(anyhow!(
+            "{:?} is not a valid variable name",
+            String::from_utf8_lossy(name)
+        ));
+    }
+    Ok((name, value))
+}
+
+pub fn is_name(name: &[u8]) -> bool {
+    match name.split_first() {
+        Some((first, rest)) => {
+            (first.is_ascii_alphabetic() || *first == b'_')
+                && rest.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'_')
+        }
+        None => false,
+    }
+}
+
+// 値を変数に代入する行にする. shell では quote 済みの値を export する.
+pub fn assignment(
+    name: &[u8],
+    value: &[u8],
+    style: &XQuoEnvStyle,
+    dialect: &XQuoDialect,
+) -> Vec<u8> {
+    match style {
+        XQuoEnvStyle::Shell => export(name, value, dialect),
+        XQuoEnvStyle::Dotenv | XQuoEnvStyle::Docker => [name, b"=", value].concat(),
+    }
+}
+
+fn export(name: &[u8], value: &[u8], dialect: &XQuoDialect) -> Vec<u8> {
+    match dialect {
+        XQuoDialect::Bash => [b"export ", name, b"=", value].concat(),
+        XQuoDialect::Fish => [b"set -gx ", name, b" ", value].concat(),
+    }
+}
+
+// quote 済みの値を --assign の変数に代入する文にする. index は入力全体での record の順番.
+// fish の list は 1 から始まるので、最初の record で作り直して残りは追加する.
+pub fn assign(
+    assign: &XQuoAssign,
+    value: &[u8],
+    index: usize,
+    dialect: &XQuoDialect,
+) -> Result<Vec<u8>> {
+    let name = assign.name.as_bytes();
+    match dialect {
+        XQuoDialect::Bash => {
+            let prefix: &[u8] = match assign.prefix {
+                Some(XQuoAssignPrefix::Local) => b"local ",
+                Some(XQuoAssignPrefix::Readonly) if assign.indexed => {
+                    return Err(anyhow!(
+                        "readonly arrays cannot be assigned element by element"
+                    ))
+                }
+                Some(XQuoAssignPrefix::Readonly) => b"readonly ",
+                None => b"",
+            };
+            if assign.indexed {
+                Ok([prefix, name, format!("[{}]=", index).as_bytes(), value].concat())
+            } else {
+                Ok([prefix, name, b"=", value].concat())
+            }
+        }
+        XQuoDialect::Fish => {
+            let set: &[u8] = match (&assign.prefix, assign.indexed && index > 0) {
+                (Some(XQuoAssignPrefix::Readonly), _) => {
+                    return Err(anyhow!("fish has no readonly variables"))
+                }
+                (_, true) => b"set -a ",
+                (Some(XQuoAssignPrefix::Local), false) => b"set -l ",
+                (None, false) => b"set ",
+            };
+            Ok([set, name, b" ", value].concat())
+        }
+    }
+}
+
+// `.env` では single quote の中は展開されないが、`'` と改行は書けないので double quote を使う.
+// double quote の中の `$` は実装によって展開されてしまうので error にする.
+pub fn dotenv_value(value: &[u8]) -> Result<Vec<u8>> {
+    if !value.iter().any(|c| *c == b'\'' || c.is_ascii_control()) {
+        return Ok([b"'", value, b"'"].concat());
+    }
+    if value.contains(&b'$') {
+        return Err(anyhow!(
+            "{:?} cannot be written in .env",
+            String::from_utf8_lossy(value)
+        ));
+    }
+    let mut ret = vec![b'"'];
+    for c in value {
+        match c {
+            b'"' | b'\\' => ret.extend_from_slice(&[b'\\', *c]),
+            b'\n' => ret.extend_from_slice(b"\\n"),
+            b'\r' => ret.extend_from_slice(b"\\r"),
+            b'\t' => ret.extend_from_slice(b"\\t"),
+            c if c.is_ascii_control() => {
+                return Err(anyhow!(
+                    "{:?} cannot be written in .env",
+                    String::from_utf8_lossy(value)
+                ))
+            }
+            c => ret.push(*c),
+        }
+    }
+    ret.push(b'"');
+    Ok(ret)
+}
+
+// docker の `--env-file` は quote を解釈せず、行の残りをそのまま値にする.
+pub fn docker_value(value: &[u8]) -> Result<Vec<u8>> {
+    if value.iter().any(|c| *c == b'\n' || *c == b'\r') {
+        return Err(anyhow!(
+            "{:?} cannot be written in an env file for docker",
+            String::from_utf8_lossy(value)
+        ));
+    }
+    Ok(value.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::{XQuoAssign, XQuoAssignPrefix, XQuoDialect, XQuoEnvStyle};
+    use crate::env::{assign, assignment, docker_value, dotenv_value, split_assignment};
+
+    #[test]
+    fn split_name_and_value() {
+        let (name, value) = split_assignment(b"PATH=/bin:/usr/bin=x").unwrap();
+        assert_eq!(name, b"PATH");
+        assert_eq!(value, b"/bin:/usr/bin=x");
+        assert_eq!(split_assignment(b"_a1=").unwrap(), (&b"_a1"[..], &b""[..]));
+        assert!(split_assignment(b"PATH").is_err());
+        assert!(split_assignment(b"1A=x").is_err());
+        assert!(split_assignment(b"A-B=x").is_err());
+        assert!(split_assignment(b"=x").is_err());
+    }
+
+    #[test]
+    fn assign_value() {
+        let shell = XQuoEnvStyle::Shell;
+        assert_eq!(
+            assignment(b"A", b"'a b'", &shell, &XQuoDialect::Bash),
+            b"export A='a b'"
+        );
+        assert_eq!(
+            assignment(b"A", b"'a b'", &shell, &XQuoDialect::Fish),
+            b"set -gx A 'a b'"
+        );
+        assert_eq!(
+            assignment(b"A", b"a b", &XQuoEnvStyle::Docker, &XQuoDialect::Bash),
+            b"A=a b"
+        );
+    }
+
+    #[test]
+    fn assign_to_variable() {
+        let bash = XQuoDialect::Bash;
+        let fish = XQuoDialect::Fish;
+        let a = XQuoAssign::new("A", false, None).unwrap();
+        assert_eq!(assign(&a, b"'a'", 3, &bash).unwrap(), b"A='a'");
+        assert_eq!(assign(&a, b"'a'", 3, &fish).unwrap(), b"set A 'a'");
+        let a = XQuoAssign::new("A", true, Some(XQuoAssignPrefix::Local)).unwrap();
+        assert_eq!(assign(&a, b"'a'", 3, &bash).unwrap(), b"local A[3]='a'");
+        assert_eq!(assign(&a, b"'a'", 0, &fish).unwrap(), b"set -l A 'a'");
+        assert_eq!(assign(&a, b"'a'", 3, &fish).unwrap(), b"set -a A 'a'");
+        let a = XQuoAssign::new("A", true, Some(XQuoAssignPrefix::Readonly)).unwrap();
+        assert!(assign(&a, b"'a'", 0, &bash).is_err());
+        assert!(XQuoAssign::new("1A", false, None).is_err());
+    }
+
+    #[test]
+    fn env_file_value() {
+        assert_eq!(dotenv_value(b"a $b").unwrap(), b"'a $b'");
+        assert_eq!(dotenv_value(b"it's\n\"\\").unwrap(), b"\"it's\\n\\\"\\\\\"");
+        assert!(dotenv_value(b"it's $HOME").is_err());
+        assert!(dotenv_value(b"\x1b").is_err());
+        assert_eq!(docker_value(b"'a b'").unwrap(), b"'a b'");
+        assert!(docker_value(b"a\nb").is_err());
+    }
+}
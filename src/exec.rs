@@ -0,0 +1,375 @@
+use anyhow::{anyhow, Context, Result};
+use crossbeam_channel::bounded;
+use std::ffi::OsString;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::cli::XQuoExecArgs;
+use crate::input::{bytes_to_os_string, os_str_to_bytes};
+use crate::quote::DoQuote;
+
+const PLACEHOLDER: &[u8] = b"{}";
+const END_OF_OPTIONS: &[u8] = b"--";
+// xargs の既定値と同じく、1 つのコマンドラインの長さを 128KiB までにする.
+const MAX_COMMAND_LINE_BYTES: usize = 128 * 1024;
+
+pub struct Exec {
+    template: Vec<Vec<u8>>,
+    batch: bool,
+    dry_run: bool,
+    failed: AtomicUsize,
+}
+
+impl Exec {
+    pub fn new(args: &XQuoExecArgs) -> Result<Exec> {
+        let mut template = Vec::<Vec<u8>>::new();
+        for arg in &args.command {
+            template.push(os_str_to_bytes(arg)?.into_owned());
+        }
+        if template.is_empty() {
+            return Err(anyhow!("command is not specified"));
+        }
+        let batch = args.batch;
+        if batch
+            && template
+                .iter()
+                .any(|arg| arg != PLACEHOLDER && find(arg, PLACEHOLDER).is_some())
+        {
+            return Err(anyhow!(
+                "{{}} must be a separate argument when running in batch mode"
+            ));
+        }
+        if args.end_of_options {
+            insert_end_of_options(&mut template);
+        }
+        Ok(Exec {
+            template,
+            batch,
+            dry_run: args.dry_run,
+            failed: AtomicUsize::new(0),
+        })
+    }
+
+    // 入力全体の行からコマンドラインを組み立てる. batch は bulk の区切りに関係なく上限まで詰める.
+    pub fn command_lines<I>(&self, records: I) -> CommandLines<'_, I>
+    where
+        I: Iterator<Item = Result<Vec<u8>>>,
+    {
+        CommandLines {
+            exec: self,
+            records,
+            pending: None,
+        }
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    // dry run で表示する、quote したコマンドラインを返す.
+    pub fn quote_command_line(
+        &self,
+        q: &dyn DoQuote,
+        out_delimiter: &str,
+        args: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let words: Vec<Vec<u8>> = args.iter().map(|arg| q.quote_bytes(arg)).collect();
+        let mut out = words.join(&b' ');
+        out.extend_from_slice(out_delimiter.as_bytes());
+        out
+    }
+
+    // コマンドラインを 1 つずつ max_procs 個の thread に渡して実行する.
+    pub fn spawn_all(
+        &self,
+        command_lines: impl Iterator<Item = Result<Vec<Vec<u8>>>>,
+        max_procs: usize,
+    ) -> Result<()> {
+        let max_procs = max_procs.max(1);
+        let (tx, rx) = bounded::<Vec<Vec<u8>>>(max_procs);
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..max_procs)
+                .map(|_| {
+                    let rx = rx.clone();
+                    scope.spawn(move || -> Result<()> {
+                        for args in rx {
+                            self.spawn(&args)?;
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            drop(rx);
+
+            let mut result = Ok(());
+            for args in command_lines {
+                match args {
+                    // 全ての thread が止まっていれば送信に失敗するので、読み込みを打ち切る.
+                    Ok(args) => {
+                        if tx.send(args).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        result = Err(err);
+                        break;
+                    }
+                }
+            }
+            drop(tx);
+            for handle in handles {
+                let spawned = handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow!("exec thread panicked")));
+                if result.is_ok() {
+                    result = spawned;
+                }
+            }
+            result
+        })
+    }
+
+    pub fn failed(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    fn command_line(&self, record: &[u8]) -> Vec<Vec<u8>> {
+        let mut args: Vec<Vec<u8>> = self
+            .template
+            .iter()
+            .map(|arg| replace(arg, PLACEHOLDER, record))
+            .collect();
+        if !self.has_placeholder() {
+            args.push(record.to_vec());
+        }
+        args
+    }
+
+    fn base_len(&self) -> usize {
+        self.template
+            .iter()
+            .filter(|arg| *arg != PLACEHOLDER)
+            .map(|arg| arg.len() + 1)
+            .sum()
+    }
+
+    fn expand_batch(&self, batch: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        let mut args = Vec::<Vec<u8>>::new();
+        for arg in &self.template {
+            if arg == PLACEHOLDER {
+                args.extend(batch.iter().map(|record| record.to_vec()));
+            } else {
+                args.push(arg.clone());
+            }
+        }
+        if !self.has_placeholder() {
+            args.extend(batch.iter().map(|record| record.to_vec()));
+        }
+        args
+    }
+
+    fn has_placeholder(&self) -> bool {
+        self.template
+            .iter()
+            .any(|arg| find(arg, PLACEHOLDER).is_some())
+    }
+
+    fn spawn(&self, args: &[Vec<u8>]) -> Result<()> {
+        let mut os_args = Vec::<OsString>::new();
+        for arg in args {
+            os_args.push(bytes_to_os_string(arg)?);
+        }
+        let status = Command::new(&os_args[0])
+            .args(&os_args[1..])
+            .stdin(Stdio::null())
+            .status()
+            .with_context(|| format!("could not run {}", os_args[0].to_string_lossy()))?;
+        if !status.success() {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+pub struct CommandLines<'a, I> {
+    exec: &'a Exec,
+    records: I,
+    // 前の batch に入りきらなかった行.
+    pending: Option<Vec<u8>>,
+}
+
+impl<I> Iterator for CommandLines<'_, I>
+where
+    I: Iterator<Item = Result<Vec<u8>>>,
+{
+    type Item = Result<Vec<Vec<u8>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.exec.batch {
+            let record = self.records.next()?;
+            return Some(record.map(|record| self.exec.command_line(&record)));
+        }
+        let mut batch = Vec::<Vec<u8>>::new();
+        let mut len = self.exec.base_len();
+        loop {
+            let record = match self.pending.take().map(Ok).or_else(|| self.records.next()) {
+                Some(Ok(record)) => record,
+                Some(Err(err)) => return Some(Err(err)),
+                None => break,
+            };
+            if !batch.is_empty() && len + record.len() + 1 > MAX_COMMAND_LINE_BYTES {
+                self.pending = Some(record);
+                break;
+            }
+            len += record.len() + 1;
+            batch.push(record);
+        }
+        if batch.is_empty() {
+            None
+        } else {
+            Some(Ok(self.exec.expand_batch(&batch)))
+        }
+    }
+}
+
+// 行が渡される最初の引数の前に `--` を挿入する. 既に `--` があれば何もしない.
+fn insert_end_of_options(template: &mut Vec<Vec<u8>>) {
+    let pos = template
+        .iter()
+        .skip(1)
+        .position(|arg| arg == PLACEHOLDER || arg == END_OF_OPTIONS)
+        .map(|pos| pos + 1);
+    match pos {
+        Some(pos) if template[pos] == END_OF_OPTIONS => {}
+        Some(pos) => template.insert(pos, END_OF_OPTIONS.to_vec()),
+        // `{}` が他の文字列と組み合わされている場合は、オプションの一部とみなす.
+        None if template.iter().any(|arg| find(arg, PLACEHOLDER).is_some()) => {}
+        None => template.push(END_OF_OPTIONS.to_vec()),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn replace(src: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::<u8>::new();
+    let mut rest = src;
+    while let Some(pos) = find(rest, from) {
+        ret.extend_from_slice(&rest[..pos]);
+        ret.extend_from_slice(to);
+        rest = &rest[pos + from.len()..];
+    }
+    ret.extend_from_slice(rest);
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::XQuoExecArgs;
+    use crate::exec::{insert_end_of_options, replace, Exec};
+    use std::ffi::OsString;
+
+    fn exec_args(command: &[&str], batch: bool) -> XQuoExecArgs {
+        XQuoExecArgs {
+            command: command.iter().map(OsString::from).collect(),
+            batch,
+            max_procs: 1,
+            dry_run: true,
+            end_of_options: false,
+        }
+    }
+
+    fn exec(command: &[&str], batch: bool) -> Exec {
+        Exec::new(&exec_args(command, batch)).unwrap()
+    }
+
+    fn to_strings(lines: Vec<Vec<Vec<u8>>>) -> Vec<Vec<String>> {
+        lines
+            .into_iter()
+            .map(|args| {
+                args.into_iter()
+                    .map(|arg| String::from_utf8(arg).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn replace_placeholder() {
+        assert_eq!(replace(b"{}", b"{}", b"test"), b"test");
+        assert_eq!(replace(b"a{}b{}", b"{}", b"test"), b"atestbtest");
+        assert_eq!(replace(b"test", b"{}", b"a"), b"test");
+    }
+
+    #[test]
+    fn command_line_per_record() {
+        let e = exec(&["ls", "-l", "{}"], false);
+        assert_eq!(
+            to_strings(vec![e.command_line(b"test test")]),
+            vec![vec!["ls", "-l", "test test"]]
+        );
+
+        let e = exec(&["mv", "{}", "{}.bak"], false);
+        assert_eq!(
+            to_strings(vec![e.command_line(b"test")]),
+            vec![vec!["mv", "test", "test.bak"]]
+        );
+
+        let e = exec(&["ls"], false);
+        assert_eq!(
+            to_strings(vec![e.command_line(b"test")]),
+            vec![vec!["ls", "test"]]
+        );
+    }
+
+    #[test]
+    fn command_lines_in_batch() {
+        let batch = |e: &Exec, records: &[&[u8]]| {
+            e.command_lines(records.iter().map(|v| Ok(v.to_vec())))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap()
+        };
+        let e = exec(&["ls", "{}", "/tmp"], true);
+        assert_eq!(
+            to_strings(batch(&e, &[b"a", b"b"])),
+            vec![vec!["ls", "a", "b", "/tmp"]]
+        );
+
+        let e = exec(&["ls"], true);
+        let long = vec![b'a'; 100 * 1024];
+        assert_eq!(batch(&e, &[&long, &long, b"b"]).len(), 2);
+    }
+
+    #[test]
+    fn placeholder_must_be_separated_in_batch() {
+        assert!(Exec::new(&exec_args(&["ls", "{}.bak"], true)).is_err());
+    }
+
+    #[test]
+    fn insert_end_of_options_before_line() {
+        let insert = |command: &[&str]| {
+            let mut template: Vec<Vec<u8>> =
+                command.iter().map(|v| v.as_bytes().to_vec()).collect();
+            insert_end_of_options(&mut template);
+            template
+                .into_iter()
+                .map(|v| String::from_utf8(v).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(insert(&["rm", "-f"]), vec!["rm", "-f", "--"]);
+        assert_eq!(
+            insert(&["mv", "-f", "{}", "/tmp"]),
+            vec!["mv", "-f", "--", "{}", "/tmp"]
+        );
+        assert_eq!(insert(&["rm", "--", "{}"]), vec!["rm", "--", "{}"]);
+        assert_eq!(insert(&["rm", "--"]), vec!["rm", "--"]);
+        assert_eq!(
+            insert(&["sort", "--output={}"]),
+            vec!["sort", "--output={}"]
+        );
+    }
+}
@@ -0,0 +1,420 @@
+use std::fmt::Write;
+
+use crate::audit::display;
+use crate::cli::XQuoDialect;
+use crate::unquote::{unquote, unquote_fish};
+
+#[derive(Debug, PartialEq)]
+pub enum Kind {
+    Literal,
+    SingleQuoted,
+    DoubleQuoted,
+    AnsiC,
+    Backslash,
+    Expansion,
+    Brace,
+    Glob,
+    Tilde,
+    Comment,
+    Metachar,
+}
+
+impl Kind {
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::Literal => "literal",
+            Kind::SingleQuoted => "single-quoted",
+            Kind::DoubleQuoted => "double-quoted",
+            Kind::AnsiC => "ansi-c",
+            Kind::Backslash => "backslash",
+            Kind::Expansion => "expansion",
+            Kind::Brace => "brace",
+            Kind::Glob => "glob",
+            Kind::Tilde => "tilde",
+            Kind::Comment => "comment",
+            Kind::Metachar => "metachar",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Segment {
+    pub start: usize,
+    pub end: usize,
+    pub kind: Kind,
+    // shell が展開する部分などは値が決まらないので None になる.
+    pub bytes: Option<Vec<u8>>,
+}
+
+pub struct Explain {
+    pub segments: Vec<Segment>,
+    pub warnings: Vec<String>,
+}
+
+// word を segment に分け、quote の外の展開やメタ文字を警告する.
+pub fn explain(word: &[u8], dialect: &XQuoDialect) -> Explain {
+    let mut segments = Vec::<Segment>::new();
+    let mut warnings = Vec::<String>::new();
+    let mut i = 0;
+    while i < word.len() {
+        let (end, kind) = segment(word, i, dialect);
+        let source = &word[i..end];
+        let bytes = match kind {
+            Kind::Literal => Some(source.to_vec()),
+            Kind::Expansion
+            | Kind::Brace
+            | Kind::Glob
+            | Kind::Tilde
+            | Kind::Comment
+            | Kind::Metachar => None,
+            _ => match dialect {
+                XQuoDialect::Bash => unquote(source).ok(),
+                XQuoDialect::Fish => unquote_fish(source).ok(),
+            },
+        };
+        let what = match kind {
+            Kind::Expansion => Some("unquoted expansion"),
+            Kind::Brace => Some("unquoted brace expansion"),
+            Kind::Glob => Some("unquoted glob"),
+            Kind::Tilde => Some("unquoted tilde"),
+            Kind::Comment => Some("unquoted comment"),
+            Kind::Metachar => Some("unquoted metacharacter that splits the word"),
+            _ if bytes.is_none() && kind == Kind::DoubleQuoted => Some("expansion in double quote"),
+            _ if bytes.is_none() => Some("unterminated quote or escape"),
+            _ => None,
+        };
+        if let Some(what) = what {
+            warnings.push(format!("{} {} at byte {}", what, show(source), i));
+        }
+        segments.push(Segment {
+            start: i,
+            end,
+            kind,
+            bytes,
+        });
+        i = end;
+    }
+    Explain { segments, warnings }
+}
+
+impl Explain {
+    // 全ての segment の値が決まる場合だけ word 全体の値を返す.
+    pub fn result(&self) -> Option<Vec<u8>> {
+        let mut ret = Vec::<u8>::new();
+        for segment in &self.segments {
+            ret.extend_from_slice(segment.bytes.as_ref()?);
+        }
+        Some(ret)
+    }
+
+    pub fn report(&self, word: &[u8]) -> String {
+        let mut ret = String::new();
+        for segment in &self.segments {
+            let _ = writeln!(
+                ret,
+                "{:>5}..{:<5} {:<14} {:<16} {}",
+                segment.start,
+                segment.end,
+                segment.kind.name(),
+                show(&word[segment.start..segment.end]),
+                match &segment.bytes {
+                    Some(bytes) => format!("\"{}\"", display(bytes)),
+                    None => "(not determined)".to_string(),
+                }
+            );
+        }
+        match self.result() {
+            Some(bytes) => {
+                let _ = writeln!(ret, "result: \"{}\"", display(&bytes));
+            }
+            None => ret.push_str("result: (not determined)\n"),
+        }
+        for warning in &self.warnings {
+            let _ = writeln!(ret, "warning: {}", warning);
+        }
+        ret
+    }
+}
+
+// word[i] から始まる segment の終わりと種類を返す.
+pub fn segment(word: &[u8], i: usize, dialect: &XQuoDialect) -> (usize, Kind) {
+    match dialect {
+        XQuoDialect::Bash => bash_segment(word, i),
+        XQuoDialect::Fish => fish_segment(word, i),
+    }
+}
+
+// word の一部はそのまま表示し、端末に影響する制御文字と不正な byte だけを escape する.
+pub fn show(source: &[u8]) -> String {
+    let mut ret = String::new();
+    for chunk in source.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c.is_control() {
+                ret.extend(c.escape_default());
+            } else {
+                ret.push(c);
+            }
+        }
+        for b in chunk.invalid() {
+            ret.push_str(&format!("\\x{:02x}", b));
+        }
+    }
+    ret
+}
+
+fn bash_segment(word: &[u8], i: usize) -> (usize, Kind) {
+    if word[i] == b'{' {
+        if let Some(end) = brace(word, i) {
+            return (end, Kind::Brace);
+        }
+    }
+    match (word[i], word.get(i + 1)) {
+        (b'\'', _) => (close(word, i + 1, b'\''), Kind::SingleQuoted),
+        (b'$', Some(b'\'')) => (close_escaped(word, i + 2, b'\''), Kind::AnsiC),
+        (b'$', Some(b'"')) => (close_escaped(word, i + 2, b'"'), Kind::DoubleQuoted),
+        (b'$', Some(c)) if is_expansion_start(*c) => (expansion(word, i), Kind::Expansion),
+        (b'`', _) => (close_escaped(word, i + 1, b'`'), Kind::Expansion),
+        (b'"', _) => (close_escaped(word, i + 1, b'"'), Kind::DoubleQuoted),
+        (b'\\', _) => ((i + 2).min(word.len()), Kind::Backslash),
+        (b'*' | b'?' | b'[', _) => (i + 1, Kind::Glob),
+        (b'~', _) if i == 0 => (name(word, i + 1), Kind::Tilde),
+        (b'#', _) if i == 0 => (word.len(), Kind::Comment),
+        (c, _) if is_metachar(c) => (i + 1, Kind::Metachar),
+        _ => (literal(word, i, b"'\"$`\\*?[{"), Kind::Literal),
+    }
+}
+
+fn fish_segment(word: &[u8], i: usize) -> (usize, Kind) {
+    match (word[i], word.get(i + 1)) {
+        (b'\'', _) => (close_escaped(word, i + 1, b'\''), Kind::SingleQuoted),
+        (b'"', _) => (close_escaped(word, i + 1, b'"'), Kind::DoubleQuoted),
+        (b'$', Some(c)) if is_expansion_start(*c) => (expansion(word, i), Kind::Expansion),
+        (b'(', _) => (parens(word, i), Kind::Expansion),
+        (b'\\', _) => (fish_escape(word, i), Kind::Backslash),
+        (b'*' | b'?' | b'{', _) => (i + 1, Kind::Glob),
+        (b'~', _) if i == 0 => (name(word, i + 1), Kind::Tilde),
+        (b'#', _) if i == 0 => (word.len(), Kind::Comment),
+        (c, _) if is_metachar(c) => (i + 1, Kind::Metachar),
+        _ => (literal(word, i, b"'\"$\\*?{"), Kind::Literal),
+    }
+}
+
+pub fn is_metachar(c: u8) -> bool {
+    matches!(
+        c,
+        b' ' | b'\t' | b'\n' | b'|' | b'&' | b';' | b'(' | b')' | b'<' | b'>'
+    )
+}
+
+fn is_expansion_start(c: u8) -> bool {
+    c.is_ascii_alphanumeric()
+        || matches!(
+            c,
+            b'_' | b'{' | b'(' | b'@' | b'*' | b'#' | b'?' | b'$' | b'!' | b'-'
+        )
+}
+
+fn literal(word: &[u8], i: usize, specials: &[u8]) -> usize {
+    word[i..]
+        .iter()
+        .position(|c| specials.contains(c) || is_metachar(*c))
+        .map_or(word.len(), |pos| i + pos.max(1))
+}
+
+// 閉じ quote が無い場合は word の最後までを 1 つの segment にする.
+fn close(word: &[u8], start: usize, quote: u8) -> usize {
+    word[start..]
+        .iter()
+        .position(|c| *c == quote)
+        .map_or(word.len(), |pos| start + pos + 1)
+}
+
+fn close_escaped(word: &[u8], start: usize, quote: u8) -> usize {
+    let mut i = start;
+    while i < word.len() {
+        match word[i] {
+            b'\\' => i += 2,
+            c if c == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    word.len()
+}
+
+// `{a,b}` や `{1..3}` のように bash が展開する brace の終わりを返す. `{a}` などは展開されない.
+fn brace(word: &[u8], i: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut comma = false;
+    let mut j = i;
+    while j < word.len() {
+        match word[j] {
+            b'{' => depth += 1,
+            b'}' if depth == 1 => {
+                return (comma || is_sequence(&word[i + 1..j])).then_some(j + 1);
+            }
+            b'}' => depth -= 1,
+            b',' if depth == 1 => comma = true,
+            b'\\' => j += 1,
+            b'\'' => j = close(word, j + 1, b'\'') - 1,
+            b'"' => j = close_escaped(word, j + 1, b'"') - 1,
+            c if is_metachar(c) => return None,
+            _ => {}
+        }
+        j += 1;
+    }
+    None
+}
+
+// `1..10`、`a..z`、`1..10..2` の形の sequence expression.
+fn is_sequence(inner: &[u8]) -> bool {
+    let parts: Vec<&str> = match std::str::from_utf8(inner) {
+        Ok(inner) => inner.split("..").collect(),
+        Err(_) => return false,
+    };
+    let is_int = |s: &str| s.strip_prefix('-').unwrap_or(s).parse::<u64>().is_ok();
+    let is_char = |s: &str| s.len() == 1 && s.as_bytes()[0].is_ascii_alphabetic();
+    let (x, y) = match parts.as_slice() {
+        [x, y] => (x, y),
+        [x, y, incr] if is_int(incr) => (x, y),
+        _ => return false,
+    };
+    (is_int(x) && is_int(y)) || (is_char(x) && is_char(y))
+}
+
+fn name(word: &[u8], start: usize) -> usize {
+    word[start..]
+        .iter()
+        .position(|c| !(c.is_ascii_alphanumeric() || *c == b'_'))
+        .map_or(word.len(), |pos| start + pos)
+}
+
+fn expansion(word: &[u8], i: usize) -> usize {
+    match word[i + 1] {
+        b'{' => close(word, i + 2, b'}'),
+        b'(' => parens(word, i + 1),
+        c if c.is_ascii_alphabetic() || c == b'_' => name(word, i + 1),
+        _ => i + 2,
+    }
+}
+
+fn parens(word: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    for (pos, c) in word[start..].iter().enumerate() {
+        match c {
+            b'(' => depth += 1,
+            b')' if depth == 1 => return start + pos + 1,
+            b')' => depth -= 1,
+            _ => {}
+        }
+    }
+    word.len()
+}
+
+fn fish_escape(word: &[u8], i: usize) -> usize {
+    let (radix, max) = match word.get(i + 1) {
+        Some(b'x' | b'X') => (16, 2),
+        Some(b'u') => (16, 4),
+        Some(b'U') => (16, 8),
+        Some(b'0'..=b'7') => return i + 1 + digits(&word[i + 1..], 8, 3),
+        _ => return (i + 2).min(word.len()),
+    };
+    i + 2 + digits(&word[i + 2..], radix, max)
+}
+
+fn digits(s: &[u8], radix: u32, max: usize) -> usize {
+    s.iter()
+        .take(max)
+        .take_while(|c| (**c as char).is_digit(radix))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::XQuoDialect;
+    use crate::explain::{explain, Kind, Segment};
+
+    #[test]
+    fn explain_bash_word() {
+        let word = b"'test'\"'\"'test'$'\\n'\\ x";
+        let explained = explain(word, &XQuoDialect::Bash);
+        let kinds: Vec<(usize, usize, &Kind)> = explained
+            .segments
+            .iter()
+            .map(|v| (v.start, v.end, &v.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (0, 6, &Kind::SingleQuoted),
+                (6, 9, &Kind::DoubleQuoted),
+                (9, 15, &Kind::SingleQuoted),
+                (15, 20, &Kind::AnsiC),
+                (20, 22, &Kind::Backslash),
+                (22, 23, &Kind::Literal),
+            ]
+        );
+        assert_eq!(explained.result().unwrap(), b"test'test\n x");
+        assert!(explained.warnings.is_empty());
+    }
+
+    #[test]
+    fn warn_on_expansions() {
+        let explained = explain(b"~/\"$HOME\"*${x}$(ls)`ls`;", &XQuoDialect::Bash);
+        assert_eq!(
+            explained.warnings,
+            [
+                "unquoted tilde ~ at byte 0",
+                "expansion in double quote \"$HOME\" at byte 2",
+                "unquoted glob * at byte 9",
+                "unquoted expansion ${x} at byte 10",
+                "unquoted expansion $(ls) at byte 14",
+                "unquoted expansion `ls` at byte 19",
+                "unquoted metacharacter that splits the word ; at byte 23",
+            ]
+        );
+        assert!(explained.result().is_none());
+        assert!(!explain(b"'test", &XQuoDialect::Bash).warnings.is_empty());
+    }
+
+    #[test]
+    fn warn_on_brace_expansions() {
+        let explained = explain(b"a{b,c}x{1..3}", &XQuoDialect::Bash);
+        assert_eq!(
+            explained.warnings,
+            [
+                "unquoted brace expansion {b,c} at byte 1",
+                "unquoted brace expansion {1..3} at byte 7",
+            ]
+        );
+        assert_eq!(explained.segments[1].kind, Kind::Brace);
+        assert!(explained.result().is_none());
+
+        for word in [
+            &b"{a}"[..],
+            b"'{a,b}'",
+            b"{}",
+            b"{a..}",
+            b"\\{a,b}",
+            b"{a,b",
+        ] {
+            let explained = explain(word, &XQuoDialect::Bash);
+            assert!(explained.warnings.is_empty(), "{:?}", explained.warnings);
+        }
+    }
+
+    #[test]
+    fn explain_fish_word() {
+        let explained = explain(b"'it\\'s'\\n(ls)", &XQuoDialect::Fish);
+        assert_eq!(
+            explained.segments[1],
+            Segment {
+                start: 7,
+                end: 9,
+                kind: Kind::Backslash,
+                bytes: Some(b"\n".to_vec()),
+            }
+        );
+        assert_eq!(explained.segments[2].kind, Kind::Expansion);
+        assert_eq!(explained.segments[0].bytes.as_deref(), Some(&b"it's"[..]));
+    }
+}
@@ -0,0 +1,35 @@
+const TERMINATOR: &str = "XQUO_EOF";
+
+// body の行と一致しない terminator を選び、`<<'TERMINATOR'` の here document にする.
+pub fn heredoc(body: &[u8]) -> Vec<u8> {
+    let lines: Vec<&[u8]> = body.split(|c| *c == b'\n').collect();
+    let mut terminator = TERMINATOR.to_string();
+    let mut n = 0;
+    while lines.contains(&terminator.as_bytes()) {
+        n += 1;
+        terminator = format!("{}_{}", TERMINATOR, n);
+    }
+    let mut ret = format!("<<'{}'\n", terminator).into_bytes();
+    ret.extend_from_slice(body);
+    if !body.is_empty() && !body.ends_with(b"\n") {
+        ret.push(b'\n');
+    }
+    ret.extend_from_slice(terminator.as_bytes());
+    ret.push(b'\n');
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::heredoc::heredoc;
+
+    #[test]
+    fn choose_terminator() {
+        assert_eq!(heredoc(b"a\nb\n"), b"<<'XQUO_EOF'\na\nb\nXQUO_EOF\n");
+        assert_eq!(heredoc(b""), b"<<'XQUO_EOF'\nXQUO_EOF\n");
+        assert_eq!(
+            heredoc(b"XQUO_EOF\nXQUO_EOF_1\nXQUO_EOF_2x\n"),
+            b"<<'XQUO_EOF_2'\nXQUO_EOF\nXQUO_EOF_1\nXQUO_EOF_2x\nXQUO_EOF_2\n"
+        );
+    }
+}
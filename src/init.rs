@@ -0,0 +1,14 @@
+use crate::cli::XQuoShell;
+
+const BASH: &str = include_str!("init/init.bash");
+const ZSH: &str = include_str!("init/init.zsh");
+const FISH: &str = include_str!("init/init.fish");
+
+// 対話 shell で読み込む widget と関数の定義.
+pub fn script(shell: &XQuoShell) -> &'static str {
+    match shell {
+        XQuoShell::Bash => BASH,
+        XQuoShell::Zsh => ZSH,
+        XQuoShell::Fish => FISH,
+    }
+}
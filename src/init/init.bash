@@ -0,0 +1,50 @@
+# xquo shell integration for bash.
+# Add the following line to ~/.bashrc:
+#
+#     eval "$(xquo init bash)"
+#
+# Ctrl-X Ctrl-F  insert files picked with $XQUO_WIDGET_FINDER (default: fzf --multi --print0)
+# Ctrl-X Ctrl-L  insert the words saved by the last `xqs COMMAND...`
+
+# Quote NUL-separated words read from stdin and print them separated by spaces.
+__xquo_join() {
+    command xquo --dialect bash -o null | {
+        local word words=
+        while IFS= read -r -d '' word; do
+            words+="$word "
+        done
+        printf '%s' "$words"
+    }
+}
+
+__xquo_insert() {
+    READLINE_LINE="${READLINE_LINE:0:READLINE_POINT}$1${READLINE_LINE:READLINE_POINT}"
+    READLINE_POINT=$((READLINE_POINT + ${#1}))
+}
+
+__xquo_insert_files() {
+    local words
+    words="$(${XQUO_WIDGET_FINDER:-fzf --multi --print0} | __xquo_join)"
+    __xquo_insert "$words"
+}
+
+__xquo_insert_saved() {
+    __xquo_insert "${__xquo_saved-}"
+}
+
+# Run COMMAND and save the quoted words of its output lines.
+# With -0, the output is split by NUL instead of newline.
+xqs() {
+    if [[ ${1-} == -0 ]]; then
+        shift
+        __xquo_saved="$("$@" | __xquo_join)"
+    else
+        __xquo_saved="$("$@" | tr '\n' '\0' | __xquo_join)"
+    fi
+    printf '%s\n' "$__xquo_saved"
+}
+
+if [[ $- == *i* ]]; then
+    bind -x '"\C-x\C-f": __xquo_insert_files'
+    bind -x '"\C-x\C-l": __xquo_insert_saved'
+fi
@@ -0,0 +1,43 @@
+# xquo shell integration for fish.
+# Add the following line to ~/.config/fish/config.fish:
+#
+#     xquo init fish | source
+#
+# Ctrl-X Ctrl-F  insert files picked with $XQUO_WIDGET_FINDER (default: fzf --multi --print0)
+# Ctrl-X Ctrl-L  insert the words saved by the last `xqs COMMAND...`
+
+# Quote NUL-separated words read from stdin and print them separated by spaces.
+function __xquo_join
+    command xquo --dialect fish -o null | while read -lz word
+        printf '%s ' $word
+    end
+end
+
+function __xquo_insert_files
+    set -l finder fzf --multi --print0
+    if set -q XQUO_WIDGET_FINDER
+        set finder (string split -n ' ' -- $XQUO_WIDGET_FINDER)
+    end
+    commandline -i -- ($finder | __xquo_join | string collect)
+    commandline -f repaint
+end
+
+function __xquo_insert_saved
+    set -q __xquo_saved; and commandline -i -- $__xquo_saved
+end
+
+# Run COMMAND and save the quoted words of its output lines.
+# With -0, the output is split by NUL instead of newline.
+function xqs
+    if test "$argv[1]" = -0
+        set -g __xquo_saved ($argv[2..-1] | __xquo_join | string collect)
+    else
+        set -g __xquo_saved ($argv | tr '\n' '\0' | __xquo_join | string collect)
+    end
+    printf '%s\n' $__xquo_saved
+end
+
+if status is-interactive
+    bind \cx\cf __xquo_insert_files
+    bind \cx\cl __xquo_insert_saved
+end
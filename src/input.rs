@@ -0,0 +1,240 @@
+use anyhow::{anyhow, Context, Result};
+use memmap2::{Mmap, MmapOptions};
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::bulk::{mapped_chunk, BulkReader};
+use crate::cli::XQuoInput as Input;
+
+// map された入力を chunk に分ける際の 1 行あたりの見積もり byte 数.
+const MAPPED_LINE_BYTES: usize = 64;
+
+pub enum Lines {
+    Read(Vec<Vec<u8>>),
+    // map された入力の一部. 行の区切りは worker 側で探す.
+    Mapped(Arc<Mmap>, Range<usize>),
+}
+
+impl Lines {
+    pub fn records(&self) -> Box<dyn Iterator<Item = &[u8]> + '_> {
+        match self {
+            Lines::Read(bufs) => Box::new(
+                bufs.iter()
+                    .map(|buf| buf.strip_suffix(b"\0").unwrap_or(buf)),
+            ),
+            Lines::Mapped(map, range) => {
+                let chunk = &map[range.clone()];
+                Box::new(
+                    chunk
+                        .strip_suffix(b"\0")
+                        .unwrap_or(chunk)
+                        .split(|b| *b == 0),
+                )
+            }
+        }
+    }
+}
+
+enum Source<'a> {
+    Mapped(Arc<Mmap>, usize),
+    Read(BulkReader<Box<dyn Read + 'a>>),
+}
+
+// 入力を順番に開き、bulk 単位で読み込む.
+pub struct InputBulks<'a> {
+    inputs: std::slice::Iter<'a, Input>,
+    current: Option<Source<'a>>,
+    bulk_lines: usize,
+}
+
+impl<'a> InputBulks<'a> {
+    pub fn new(inputs: &'a [Input], bulk_lines: usize) -> InputBulks<'a> {
+        InputBulks {
+            inputs: inputs.iter(),
+            current: None,
+            bulk_lines,
+        }
+    }
+
+    pub fn from_reader(reader: impl Read + 'a, bulk_lines: usize) -> InputBulks<'a> {
+        InputBulks {
+            inputs: [].iter(),
+            current: Some(Source::Read(BulkReader::new(Box::new(reader), bulk_lines))),
+            bulk_lines,
+        }
+    }
+
+    fn open(&self, input: &Input) -> Result<Source<'a>> {
+        let source = match input {
+            Input::Stdin => match map_file(stdin_file())? {
+                Some(map) => Source::Mapped(Arc::new(map), 0),
+                None => Source::Read(BulkReader::new(Box::new(std::io::stdin()), self.bulk_lines)),
+            },
+            Input::File(path) => {
+                let file = File::open(path)
+                    .with_context(|| format!("could not open {}", path.display()))?;
+                match map_file(file.try_clone().ok())
+                    .with_context(|| format!("could not map {}", path.display()))?
+                {
+                    Some(map) => Source::Mapped(Arc::new(map), 0),
+                    None => Source::Read(BulkReader::new(Box::new(file), self.bulk_lines)),
+                }
+            }
+        };
+        Ok(source)
+    }
+}
+
+impl<'a> Iterator for InputBulks<'a> {
+    type Item = Result<Lines>;
+
+    fn next(&mut self) -> Option<Result<Lines>> {
+        loop {
+            match &mut self.current {
+                Some(Source::Mapped(map, start)) => {
+                    let size = self.bulk_lines * MAPPED_LINE_BYTES;
+                    if let Some(range) = mapped_chunk(map, *start, size, 0) {
+                        *start = range.end;
+                        return Some(Ok(Lines::Mapped(map.clone(), range)));
+                    }
+                }
+                Some(Source::Read(reader)) => match reader.read(0) {
+                    Ok((_, 0)) => {}
+                    Ok((lines, _)) => return Some(Ok(Lines::Read(lines))),
+                    Err(err) => return Some(Err(err).context("could not read lines")),
+                },
+                None => {}
+            }
+            let input = self.inputs.next()?;
+            match self.open(input) {
+                Ok(source) => self.current = Some(source),
+                Err(err) => {
+                    self.inputs = [].iter();
+                    self.current = None;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn stdin_file() -> Option<File> {
+    use std::os::fd::AsFd;
+    std::io::stdin()
+        .as_fd()
+        .try_clone_to_owned()
+        .ok()
+        .map(File::from)
+}
+
+#[cfg(windows)]
+fn stdin_file() -> Option<File> {
+    use std::os::windows::io::AsHandle;
+    std::io::stdin()
+        .as_handle()
+        .try_clone_to_owned()
+        .ok()
+        .map(File::from)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn stdin_file() -> Option<File> {
+    None
+}
+
+// 通常のファイルであれば、現在の位置から末尾までを map する.
+fn map_file(file: Option<File>) -> Result<Option<Mmap>> {
+    let Some(mut file) = file else {
+        return Ok(None);
+    };
+    match file.metadata() {
+        Ok(meta) if meta.is_file() => {}
+        _ => return Ok(None),
+    }
+    let Ok(offset) = file.stream_position() else {
+        return Ok(None);
+    };
+    let Ok(len) = file.seek(SeekFrom::End(0)) else {
+        return Ok(None);
+    };
+    if offset >= len {
+        return Ok(None);
+    }
+    // SAFETY: 処理中に入力ファイルが書き換えられないことを前提とする(他の mmap 利用ツールと同様).
+    let map = unsafe { MmapOptions::new().offset(offset).map(&file) }?;
+    Ok(Some(map))
+}
+
+// --files0-from で指定されたファイルから null 区切りのファイル名を読み込む.
+pub fn read_files0_from(path: &Path) -> Result<Vec<Input>> {
+    let from_stdin = path == Path::new("-");
+    let mut buf = Vec::<u8>::new();
+    if from_stdin {
+        std::io::stdin().read_to_end(&mut buf)
+    } else {
+        File::open(path).and_then(|mut file| file.read_to_end(&mut buf))
+    }
+    .with_context(|| format!("could not read file names from {}", path.display()))?;
+
+    let names = buf.strip_suffix(b"\0").unwrap_or(&buf);
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+    names
+        .split(|b| *b == 0)
+        .enumerate()
+        .map(|(i, name)| {
+            if name.is_empty() {
+                return Err(anyhow!(
+                    "{}:{}: invalid zero-length file name",
+                    path.display(),
+                    i + 1
+                ));
+            }
+            if from_stdin && name == b"-" {
+                return Err(anyhow!(
+                    "when reading file names from stdin, no file name of '-' allowed"
+                ));
+            }
+            Ok(Input::File(bytes_to_path(name)?))
+        })
+        .collect()
+}
+
+fn bytes_to_path(name: &[u8]) -> Result<PathBuf> {
+    Ok(PathBuf::from(bytes_to_os_string(name).with_context(
+        || "could not decode file name".to_string(),
+    )?))
+}
+
+#[cfg(unix)]
+pub fn bytes_to_os_string(s: &[u8]) -> Result<OsString> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(OsStr::from_bytes(s).to_os_string())
+}
+
+#[cfg(not(unix))]
+pub fn bytes_to_os_string(s: &[u8]) -> Result<OsString> {
+    Ok(OsString::from(std::str::from_utf8(s)?))
+}
+
+#[cfg(unix)]
+pub fn os_str_to_bytes(s: &OsStr) -> Result<Cow<'_, [u8]>> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(Cow::Borrowed(s.as_bytes()))
+}
+
+#[cfg(not(unix))]
+pub fn os_str_to_bytes(s: &OsStr) -> Result<Cow<'_, [u8]>> {
+    Ok(Cow::Borrowed(
+        s.to_str()
+            .with_context(|| format!("could not decode {:?}", s))?
+            .as_bytes(),
+    ))
+}
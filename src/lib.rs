@@ -1,53 +1,236 @@
+mod audit;
 mod bulk;
+mod color;
+mod convert;
+mod dash;
+mod encoding;
+mod env;
+mod exec;
+mod explain;
+mod heredoc;
+mod init;
+mod input;
+mod normalize;
+mod pipeline;
 mod quote;
+mod repl;
+mod script;
+mod split;
+mod unquote;
+mod verify;
 
 pub mod cli {
-    use anyhow::{Context, Result};
-    use crossbeam_channel::{bounded, Receiver, Sender};
+    use anyhow::{anyhow, Context, Result};
     use is_terminal::IsTerminal;
+    use std::borrow::Cow;
+    use std::cell::Cell;
+    use std::ffi::OsString;
     use std::io::prelude::*;
     use std::io::BufWriter;
-    use std::sync::mpsc;
-    use std::thread;
+    use std::path::{Path, PathBuf};
 
-    use crate::bulk::BulkReader;
+    use crate::audit::Audit;
+    use crate::color::colorize;
+    use crate::convert::convert;
+    use crate::dash::protect_leading_dash;
+    use crate::encoding::{decode, encode, Codec};
+    use crate::env::{assign, assignment, docker_value, dotenv_value, is_name, split_assignment};
+    use crate::exec::Exec;
+    use crate::explain::explain;
+    use crate::heredoc::heredoc;
+    use crate::init::script;
+    use crate::input::{os_str_to_bytes, read_files0_from, InputBulks, Lines};
+    use crate::normalize::normalize;
+    use crate::pipeline::Pipeline;
+    use crate::quote::quoter;
     use crate::quote::DoQuote;
-    use crate::quote::QuoteBasic;
-    use crate::quote::QuotePrintable;
-    struct ChanChanTx<T, U> {
-        payload: T,
-        tx: Sender<U>,
-    }
-    struct ChanChanRx<U> {
-        rx: Receiver<U>,
-    }
+    use crate::repl::Repl;
+    use crate::script::header;
+    use crate::split::{split, Token};
+    use crate::verify::verify;
 
     pub enum XQuoOutDelimiter {
         Null,
         Lf,
     }
 
+    pub enum XQuoLeadingDash {
+        PrefixDotSlash,
+        Error,
+        Warn,
+    }
+
+    #[derive(Clone, Copy)]
+    pub enum XQuoDialect {
+        Bash,
+        Fish,
+    }
+
+    pub enum XQuoShell {
+        Bash,
+        Zsh,
+        Fish,
+    }
+
+    pub enum XQuoNormalize {
+        Nfc,
+        Nfd,
+        Nfkc,
+        Nfkd,
+        None,
+    }
+
+    pub enum XQuoUnmappable {
+        Fail,
+        Replace,
+        Escape,
+    }
+
+    #[derive(Clone)]
+    pub struct XQuoEncoding(Codec);
+
+    impl XQuoEncoding {
+        pub fn for_label(label: &str) -> Result<XQuoEncoding> {
+            Ok(XQuoEncoding(Codec::for_label(label)?))
+        }
+    }
+
+    pub enum XQuoColor {
+        Auto,
+        Always,
+        Never,
+    }
+
+    pub enum XQuoFormat {
+        Quote,
+        Env,
+        Heredoc,
+    }
+
+    pub enum XQuoEnvStyle {
+        Shell,
+        Dotenv,
+        Docker,
+    }
+
+    pub enum XQuoAssignPrefix {
+        Local,
+        Readonly,
+    }
+
+    pub struct XQuoAssign {
+        pub name: String,
+        pub indexed: bool,
+        pub prefix: Option<XQuoAssignPrefix>,
+    }
+
+    impl XQuoAssign {
+        pub fn new(
+            name: &str,
+            indexed: bool,
+            prefix: Option<XQuoAssignPrefix>,
+        ) -> Result<XQuoAssign> {
+            if !is_name(name.as_bytes()) {
+                return Err(anyhow!("{:?} is not a valid variable name", name));
+            }
+            Ok(XQuoAssign {
+                name: name.to_string(),
+                indexed,
+                prefix,
+            })
+        }
+    }
+
+    pub struct XQuoScript {
+        pub args: Vec<OsString>,
+        pub confirm: bool,
+    }
+
+    pub enum XQuoVerifyShell {
+        Bash,
+        Sh,
+        Zsh,
+        Fish,
+    }
+
+    pub enum XQuoInput {
+        Stdin,
+        File(PathBuf),
+    }
+
+    impl XQuoInput {
+        pub fn from_files0(path: &Path) -> Result<Vec<XQuoInput>> {
+            read_files0_from(path)
+        }
+    }
+
     pub struct XQuoArgs {
         pub no_escape: bool,
+        pub dialect: XQuoDialect,
         pub out_delimiter: XQuoOutDelimiter,
         pub workers: u8,
         pub bulk_lines: usize,
         pub input_from_tty: bool,
+        pub unordered: bool,
+        pub leading_dash: Option<XQuoLeadingDash>,
+        pub normalize: XQuoNormalize,
+        pub input_encoding: Option<XQuoEncoding>,
+        pub output_encoding: Option<XQuoEncoding>,
+        pub unmappable: XQuoUnmappable,
+        pub verify: Option<XQuoVerifyShell>,
+        pub color: XQuoColor,
+        pub format: XQuoFormat,
+        pub env_style: XQuoEnvStyle,
+        pub assign: Option<XQuoAssign>,
+        pub script: Option<XQuoScript>,
+    }
+
+    pub struct XQuoExecArgs {
+        pub command: Vec<OsString>,
+        pub batch: bool,
+        pub max_procs: u8,
+        pub dry_run: bool,
+        pub end_of_options: bool,
+    }
+
+    pub enum XQuoAuditFormat {
+        Text,
+        Json,
+    }
+
+    pub struct XQuoAuditArgs {
+        pub format: XQuoAuditFormat,
+        pub max: Vec<(String, usize)>,
     }
 
     pub struct XQuo {
         no_escape: bool,
+        dialect: XQuoDialect,
         out_delimiter: String,
         workers: u8,
         bulk_lines: usize,
         input_from_tty: bool,
+        unordered: bool,
+        leading_dash: Option<XQuoLeadingDash>,
+        normalize: XQuoNormalize,
+        input_encoding: Option<XQuoEncoding>,
+        output_encoding: Option<XQuoEncoding>,
+        unmappable: XQuoUnmappable,
+        verify: Option<XQuoVerifyShell>,
+        color: bool,
+        format: XQuoFormat,
+        env_style: XQuoEnvStyle,
+        assign: Option<XQuoAssign>,
+        script: Option<XQuoScript>,
     }
 
     const EXMAPLES_MESSAGE: &str = "
-xquo reads lines from standard input.
+xquo reads lines from standard input or files.
 
 EXAMPLES:
     $ find . -type f -print0 | xqua
+    $ xquo list.nul
+    $ xquo repl
 
 For more information try --help
 
@@ -57,6 +240,7 @@ For more information try --help
         pub fn new(args: XQuoArgs) -> XQuo {
             XQuo {
                 no_escape: args.no_escape,
+                dialect: args.dialect,
                 out_delimiter: match args.out_delimiter {
                     XQuoOutDelimiter::Null => "\0".to_string(),
                     // XQuoOutDelimiter::Lf => "\n".to_string(),
@@ -65,113 +249,530 @@ For more information try --help
                 workers: args.workers,
                 bulk_lines: args.bulk_lines,
                 input_from_tty: args.input_from_tty,
+                unordered: args.unordered,
+                leading_dash: args.leading_dash,
+                normalize: args.normalize,
+                input_encoding: args.input_encoding,
+                output_encoding: args.output_encoding,
+                unmappable: args.unmappable,
+                verify: args.verify,
+                format: args.format,
+                env_style: args.env_style,
+                assign: args.assign,
+                script: args.script,
+                color: match args.color {
+                    // NO_COLOR が空でなければ色を付けない(https://no-color.org/).
+                    XQuoColor::Auto => {
+                        std::io::stdout().is_terminal()
+                            && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    }
+                    XQuoColor::Always => true,
+                    XQuoColor::Never => false,
+                },
             }
         }
+        pub fn quote_inputs(
+            &self,
+            inputs: &[XQuoInput],
+            writer: impl std::io::Write + Send,
+        ) -> Result<()> {
+            if self.is_input_from_tty(inputs) {
+                // 端末から実行された場合は対話モードにする.
+                if std::io::stdout().is_terminal() {
+                    return self.repl();
+                }
+                return print_examples(writer);
+            }
+            self.run(InputBulks::new(inputs, self.bulk_lines), writer)
+        }
+
         pub fn quote(
             &self,
             reader: impl std::io::Read,
-            writer: std::io::Stdout,
-        ) -> Result<(), Box<dyn std::error::Error>> {
-            if !self.input_from_tty && std::io::stdin().is_terminal() {
+            writer: impl std::io::Write + Send,
+        ) -> Result<()> {
+            if self.is_input_from_tty(&[XQuoInput::Stdin]) {
+                return print_examples(writer);
+            }
+            self.run(InputBulks::from_reader(reader, self.bulk_lines), writer)
+        }
+
+        pub fn quote_args(&self, args: &[OsString], writer: impl std::io::Write) -> Result<()> {
+            self.check_unmappable_escape(&self.dialect, true)?;
+            let q = self.quoter();
+            let mut words = Vec::<Vec<u8>>::new();
+            for arg in args {
+                let arg = os_str_to_bytes(arg)?;
+                words.push(self.colorize(q.quote_bytes(&self.prepare(&arg)?)));
+            }
+            let mut out = words.join(&b' ');
+            out.push(b'\n');
+            let mut buf_writer = BufWriter::new(writer);
+            buf_writer.write_all(&self.encode_output(out)?)?;
+            buf_writer.flush()?;
+            Ok(())
+        }
+
+        pub fn exec(
+            &self,
+            inputs: &[XQuoInput],
+            args: XQuoExecArgs,
+            writer: impl std::io::Write + Send,
+        ) -> Result<()> {
+            if self.is_input_from_tty(inputs) {
+                return print_examples(writer);
+            }
+            let exec = Exec::new(&args)?;
+            // --max-procs は bulk ではなくコマンドラインごとに並列に実行する.
+            let records = InputBulks::new(inputs, self.bulk_lines).flat_map(|lines| match lines {
+                Ok(lines) => lines
+                    .records()
+                    .map(|record| self.prepare(record).map(|v| v.into_owned()))
+                    .collect::<Vec<_>>(),
+                Err(err) => vec![Err(err)],
+            });
+            let command_lines = exec.command_lines(records);
+            if exec.dry_run() {
+                self.check_unmappable_escape(&self.dialect, true)?;
+                let q = self.quoter();
                 let mut buf_writer = BufWriter::new(writer);
-                buf_writer
-                    .write_all(EXMAPLES_MESSAGE.to_string().as_bytes())
-                    .unwrap();
-                return Ok(());
+                for args in command_lines {
+                    let out = exec.quote_command_line(q.as_ref(), &self.out_delimiter, &args?);
+                    buf_writer.write_all(&self.encode_output(out)?)?;
+                }
+                buf_writer.flush()?;
+            } else {
+                exec.spawn_all(command_lines, args.max_procs as usize)?;
             }
-            let mut buf_reader = BulkReader::new(reader, self.bulk_lines);
-
-            // let (out_tx, out_rx) = bounded::<String>(3);
-            // let (in_tx, in_rx) = bounded::<String>(3);
-            let (out_tx, out_rx) = bounded::<ChanChanTx<Vec<Vec<u8>>, String>>(0);
-            let (in_tx, in_rx) = mpsc::sync_channel::<ChanChanRx<String>>(self.workers as usize);
-
-            for _i in 0..self.workers {
-                let no_escape = self.no_escape;
-                let out_delimiter = self.out_delimiter.clone();
-                let out_rx = out_rx.clone();
-                thread::spawn(move || {
-                    let q = if !no_escape {
-                        Box::new(QuotePrintable {}) as Box<dyn DoQuote>
-                    } else {
-                        Box::new(QuoteBasic {}) as Box<dyn DoQuote>
-                    };
-                    for chan_chan in out_rx {
-                        //let mut s = Vec::<String>::new();
-                        let mut s = Vec::<String>::new();
-                        for buf in chan_chan.payload {
-                            let mut line = std::str::from_utf8(&buf).unwrap().to_string();
-                            if line.ends_with('\0') {
-                                line.truncate(line.len() - 1)
-                            }
-                            s.push(q.quote(line));
+            match exec.failed() {
+                0 => Ok(()),
+                n => Err(anyhow!("{} command(s) exited with non-zero status", n)),
+            }
+        }
+
+        pub fn audit(
+            &self,
+            inputs: &[XQuoInput],
+            args: XQuoAuditArgs,
+            writer: impl std::io::Write,
+        ) -> Result<()> {
+            if self.is_input_from_tty(inputs) {
+                return print_examples(writer);
+            }
+            let mut audit = Audit::new(&args.max, &self.normalize)?;
+            for lines in InputBulks::new(inputs, self.bulk_lines) {
+                for record in lines?.records() {
+                    match &self.input_encoding {
+                        Some(encoding) => {
+                            audit.add(&decode(record, &encoding.0, &self.unmappable)?)
                         }
-                        // TODO: error を受信する用の thread を作成.
-                        chan_chan
-                            .tx
-                            .send(s.join(&out_delimiter) + &out_delimiter)
-                            .with_context(|| "could not send lines to printer thread".to_string())
-                            .unwrap_or_else(|err| {
-                                eprintln!("{}", err);
-                                std::process::exit(1);
-                            });
+                        None => audit.add(record),
                     }
-                });
+                }
+            }
+            let mut buf_writer = BufWriter::new(writer);
+            buf_writer.write_all(audit.report(&args.format)?.as_bytes())?;
+            buf_writer.flush()?;
+            let violations = audit.violations();
+            if !violations.is_empty() {
+                return Err(anyhow!("audit failed: {}", violations.join(", ")));
             }
+            Ok(())
+        }
 
-            let t = thread::spawn(move || {
-                let mut buf_writer = BufWriter::new(writer);
-                for line in in_rx {
-                    // TODO: error を受信する用の thread を作成.
-                    buf_writer
-                        .write_all(line.rx.recv().unwrap().as_bytes())
-                        //.with_context(|| format!("could not print lines"))
-                        .unwrap_or_else(|err| {
-                            if let Some(x) = err.raw_os_error() {
-                                // println!("{}", x);
-                                if x != 32 {
-                                    eprintln!("{}", err);
-                                }
-                                std::process::exit(1);
+        pub fn explain(&self, word: &OsString, writer: impl std::io::Write) -> Result<()> {
+            let word = os_str_to_bytes(word)?;
+            let mut buf_writer = BufWriter::new(writer);
+            buf_writer.write_all(explain(&word, &self.dialect).report(&word).as_bytes())?;
+            buf_writer.flush()?;
+            Ok(())
+        }
+
+        // コマンドラインを word に分け、値を NUL 区切りで出力する. operator は標準エラーに報告する.
+        pub fn split(&self, inputs: &[XQuoInput], writer: impl std::io::Write) -> Result<()> {
+            if self.is_input_from_tty(inputs) {
+                return print_examples(writer);
+            }
+            let mut buf_writer = BufWriter::new(writer);
+            let mut index = 0;
+            for lines in InputBulks::new(inputs, self.bulk_lines) {
+                for record in lines?.records() {
+                    index += 1;
+                    // 正規化は quote などの解釈を変えないように、分けた後の word ごとに行う.
+                    let line = self.decode_input(record)?;
+                    let tokens = split(&line, &self.dialect)
+                        .with_context(|| format!("could not split line {}", index))?;
+                    for token in tokens {
+                        match token {
+                            Token::Word(word) => {
+                                buf_writer.write_all(&normalize(&word, &self.normalize))?;
+                                buf_writer.write_all(b"\0")?;
                             }
-                            eprintln!("{}", err);
-                            std::process::exit(1);
-                        });
+                            Token::Operator(op) => eprintln!(
+                                "xquo: warning: operator {:?} in line {} is not a word",
+                                String::from_utf8_lossy(&op),
+                                index
+                            ),
+                        }
+                    }
                 }
-            });
+            }
+            buf_writer.flush()?;
+            Ok(())
+        }
 
-            loop {
-                //let mut buf = Vec::<u8>::new();
-                //if buf_reader.read_until(0, &mut buf)? == 0 {
-                //    break;
-                //}
-                //out_tx.send(buf).unwrap();
-                let (bulk, line_cnt) = buf_reader.read(0);
-                if line_cnt == 0 {
-                    break;
+        // from の dialect で quote されたコマンドラインを、to の dialect で quote し直す.
+        pub fn convert(
+            &self,
+            inputs: &[XQuoInput],
+            from: &XQuoDialect,
+            to: &XQuoDialect,
+            writer: impl std::io::Write,
+        ) -> Result<()> {
+            if self.is_input_from_tty(inputs) {
+                return print_examples(writer);
+            }
+            self.check_unmappable_escape(to, true)?;
+            let q = quoter(to, self.no_escape);
+            let mut buf_writer = BufWriter::new(writer);
+            let mut index = 0;
+            for lines in InputBulks::new(inputs, self.bulk_lines) {
+                for record in lines?.records() {
+                    index += 1;
+                    let line = self.decode_input(record)?;
+                    let mut out = convert(&line, from, q.as_ref(), &self.normalize)
+                        .with_context(|| format!("could not convert line {}", index))?;
+                    out.extend_from_slice(self.out_delimiter.as_bytes());
+                    buf_writer.write_all(&self.encode_output(out)?)?;
+                }
+            }
+            buf_writer.flush()?;
+            Ok(())
+        }
+
+        // /proc/PID/cmdline をコマンドラインに、/proc/PID/environ を export する行にする.
+        pub fn pid(&self, pid: u32, environ: bool, writer: impl std::io::Write) -> Result<()> {
+            let shell_output = !environ || matches!(self.env_style, XQuoEnvStyle::Shell);
+            self.check_unmappable_escape(&self.dialect, shell_output)?;
+            let name = if environ { "environ" } else { "cmdline" };
+            let path = PathBuf::from(format!("/proc/{}/{}", pid, name));
+            let content = std::fs::read(&path)
+                .with_context(|| format!("could not read {}", path.display()))?;
+            let content = content.strip_suffix(b"\0").unwrap_or(&content);
+            if !environ && content.is_empty() {
+                return Err(anyhow!("process {} has no command line", pid));
+            }
+            let q = self.quoter();
+            let mut out = Vec::<u8>::new();
+            if environ {
+                for record in content.split(|b| *b == 0).filter(|v| !v.is_empty()) {
+                    // `export -f` の BASH_FUNC_f%% のように変数名として使えないものは飛ばす.
+                    let (name, value) = match split_assignment(record) {
+                        Ok(v) => v,
+                        Err(err) => {
+                            eprintln!("xquo: warning: {}, skipped", err);
+                            continue;
+                        }
+                    };
+                    let mut value = self.env_value(q.as_ref(), &self.prepare(value)?)?;
+                    if matches!(self.env_style, XQuoEnvStyle::Shell) {
+                        value = self.colorize(value);
+                    }
+                    out.extend_from_slice(&assignment(
+                        name,
+                        &value,
+                        &self.env_style,
+                        &self.dialect,
+                    ));
+                    out.push(b'\n');
                 }
-                // TODO: error を受信する用の thread を作成.
-                let (tx, rx) = bounded::<String>(0);
-                out_tx
-                    .send(ChanChanTx { payload: bulk, tx })
-                    .with_context(|| "could not send lines to quote thread".to_string())
-                    .unwrap_or_else(|err| {
-                        eprintln!("{}", err);
-                        std::process::exit(1);
-                    });
-                in_tx
-                    .send(ChanChanRx { rx })
-                    .with_context(|| "could not send lines to quote thread".to_string())
-                    .unwrap_or_else(|err| {
-                        eprintln!("{}", err);
-                        std::process::exit(1);
-                    });
-            }
-            drop(out_tx);
-            drop(in_tx);
-            t.join().unwrap();
+            } else {
+                let mut words = Vec::<Vec<u8>>::new();
+                for arg in content.split(|b| *b == 0) {
+                    words.push(self.colorize(q.quote_bytes(&self.prepare(arg)?)));
+                }
+                out = words.join(&b' ');
+                out.push(b'\n');
+            }
+            let mut buf_writer = BufWriter::new(writer);
+            buf_writer.write_all(&self.encode_output(out)?)?;
+            buf_writer.flush()?;
+            Ok(())
+        }
+
+        pub fn repl(&self) -> Result<()> {
+            Repl::new(self.dialect, !self.no_escape).run()
+        }
+
+        pub fn init(&self, shell: &XQuoShell, writer: impl std::io::Write) -> Result<()> {
+            let mut buf_writer = BufWriter::new(writer);
+            buf_writer.write_all(script(shell).as_bytes())?;
+            buf_writer.flush()?;
+            Ok(())
+        }
+
+        fn is_input_from_tty(&self, inputs: &[XQuoInput]) -> bool {
+            let from_stdin = inputs.iter().any(|v| matches!(v, XQuoInput::Stdin));
+            from_stdin && !self.input_from_tty && std::io::stdin().is_terminal()
+        }
 
+        fn run(
+            &self,
+            bulks: impl Iterator<Item = Result<Lines>>,
+            writer: impl std::io::Write + Send,
+        ) -> Result<()> {
+            self.check_unmappable_escape(&self.dialect, self.is_shell_output())?;
+            let script = match &self.script {
+                Some(script) => script,
+                None => return self.run_records(bulks, writer),
+            };
+            // quote しただけの行はコマンドとして実行されてしまうので、shell の文になる形式だけを使う.
+            // dotenv や docker の値、here document は shell の文として正しく評価されない.
+            if !self.is_shell_output()
+                || matches!(self.format, XQuoFormat::Quote) && self.assign.is_none()
+            {
+                return Err(anyhow!(
+                    "--script needs --assign, --assign-indexed or --format env with --env-style shell"
+                ));
+            }
+            // local は関数の中でしか使えない.
+            if matches!(&self.assign, Some(assign) if matches!(assign.prefix, Some(XQuoAssignPrefix::Local)))
+            {
+                return Err(anyhow!("--script cannot use --assign-prefix local"));
+            }
+            if self.out_delimiter != "\n" {
+                return Err(anyhow!("scripts need lines delimited by lf"));
+            }
+            // header に record の数を書くために、全ての行を読んでから書き出す.
+            let records = Cell::new(0);
+            let bulks = bulks.inspect(|lines| {
+                if let Ok(lines) = lines {
+                    records.set(records.get() + lines.records().count());
+                }
+            });
+            let mut body = Vec::<u8>::new();
+            self.run_records(bulks, &mut body)?;
+            let head = header(&self.dialect, &script.args, records.get(), script.confirm);
+            let mut buf_writer = BufWriter::new(writer);
+            buf_writer.write_all(&self.encode_output(head)?)?;
+            buf_writer.write_all(&body)?;
+            buf_writer.flush()?;
             Ok(())
         }
+
+        fn run_records(
+            &self,
+            bulks: impl Iterator<Item = Result<Lines>>,
+            writer: impl std::io::Write + Send,
+        ) -> Result<()> {
+            let q = self.quoter();
+            if matches!(self.format, XQuoFormat::Heredoc) {
+                if matches!(self.dialect, XQuoDialect::Fish) {
+                    return Err(anyhow!("fish does not support here documents"));
+                }
+                if self.out_delimiter != "\n" {
+                    return Err(anyhow!("here documents need lines delimited by lf"));
+                }
+                // 改行を含む行は -n でも escape しないと here document の行が分かれてしまう.
+                let q = quoter(&self.dialect, false);
+                // terminator を選ぶために全ての行を読んでから書き出す.
+                let mut body = Vec::<u8>::new();
+                let pipeline = Pipeline {
+                    workers: self.workers as usize,
+                    unordered: self.unordered,
+                };
+                pipeline.run(bulks, &mut body, |lines| {
+                    self.quote_bulk(q.as_ref(), lines, 0)
+                })?;
+                let mut buf_writer = BufWriter::new(writer);
+                buf_writer.write_all(&heredoc(&body))?;
+                buf_writer.flush()?;
+                return Ok(());
+            }
+            let pipeline = Pipeline {
+                workers: self.workers as usize,
+                unordered: self.unordered,
+            };
+            if matches!(&self.assign, Some(assign) if assign.indexed) {
+                // fish の list には順番に追加するので、並べ直さないと要素の順番が変わる.
+                if self.unordered && matches!(self.dialect, XQuoDialect::Fish) {
+                    return Err(anyhow!(
+                        "--assign-indexed for fish cannot be used with --unordered"
+                    ));
+                }
+                // 添字は入力全体での順番なので、読み込むときに bulk の最初の添字を数えておく.
+                let mut next = 0;
+                let bulks = bulks.map(|lines| {
+                    lines.map(|lines| {
+                        let first = next;
+                        next += lines.records().count();
+                        (lines, first)
+                    })
+                });
+                return pipeline.run(bulks, writer, |(lines, first)| {
+                    self.quote_bulk(q.as_ref(), lines, first)
+                });
+            }
+            pipeline.run(bulks, writer, |lines| self.quote_bulk(q.as_ref(), lines, 0))
+        }
+
+        fn quoter(&self) -> Box<dyn DoQuote> {
+            quoter(&self.dialect, self.no_escape)
+        }
+
+        fn quote_bulk(&self, q: &dyn DoQuote, lines: Lines, first: usize) -> Result<Vec<u8>> {
+            let records = self.prepare_records(&lines)?;
+            // env では `NAME=value` の値だけを quote する.
+            let mut names = Vec::<Option<&[u8]>>::new();
+            let mut values = Vec::<&[u8]>::new();
+            for record in &records {
+                match self.format {
+                    XQuoFormat::Quote | XQuoFormat::Heredoc => {
+                        names.push(None);
+                        values.push(record);
+                    }
+                    XQuoFormat::Env => {
+                        let (name, value) = split_assignment(record)?;
+                        names.push(Some(name));
+                        values.push(value);
+                    }
+                }
+            }
+            let mut words = Vec::<Vec<u8>>::new();
+            for value in &values {
+                match self.format {
+                    XQuoFormat::Quote => words.push(self.quote_word(q, value)?),
+                    XQuoFormat::Env => words.push(self.env_value(q, value)?),
+                    // here document の中は展開されないので、改行を含む行だけを quote する.
+                    XQuoFormat::Heredoc if value.contains(&b'\n') => {
+                        words.push(self.quote_word(q, value)?)
+                    }
+                    XQuoFormat::Heredoc => words.push(value.to_vec()),
+                }
+            }
+            if let Some(shell) = &self.verify {
+                if !self.is_shell_output() {
+                    return Err(anyhow!("--verify can only check words quoted for a shell"));
+                }
+                // fish の quote は bash 系の shell では解釈が異なる.
+                if matches!(self.dialect, XQuoDialect::Fish)
+                    != matches!(shell, XQuoVerifyShell::Fish)
+                {
+                    return Err(anyhow!("--verify must use a shell of the --dialect"));
+                }
+                let words: Vec<&[u8]> = words.iter().map(|v| v.as_slice()).collect();
+                verify(shell, &values, &words)?;
+            }
+            let mut printed = Vec::<Vec<u8>>::new();
+            for (i, (name, word)) in names.into_iter().zip(words).enumerate() {
+                let word = if self.is_shell_output() {
+                    self.colorize(word)
+                } else {
+                    word
+                };
+                printed.push(match (name, &self.assign) {
+                    (Some(name), _) => assignment(name, &word, &self.env_style, &self.dialect),
+                    (None, Some(to)) => assign(to, &word, first + i, &self.dialect)?,
+                    (None, None) => word,
+                });
+            }
+            let mut out = printed.join(self.out_delimiter.as_bytes());
+            out.extend_from_slice(self.out_delimiter.as_bytes());
+            self.encode_output(out)
+        }
+
+        fn quote_word(&self, q: &dyn DoQuote, record: &[u8]) -> Result<Vec<u8>> {
+            // escape では UTF-8 として不正な byte 列も `$'\xNN'` で quote する.
+            if matches!(self.unmappable, XQuoUnmappable::Escape) {
+                return Ok(q.quote_bytes(record));
+            }
+            let line = std::str::from_utf8(record)
+                .with_context(|| "could not decode line as UTF-8".to_string())?;
+            Ok(q.quote(line).into_bytes())
+        }
+
+        fn env_value(&self, q: &dyn DoQuote, value: &[u8]) -> Result<Vec<u8>> {
+            match self.env_style {
+                XQuoEnvStyle::Shell => self.quote_word(q, value),
+                XQuoEnvStyle::Dotenv => dotenv_value(value),
+                XQuoEnvStyle::Docker => docker_value(value),
+            }
+        }
+
+        fn is_shell_output(&self) -> bool {
+            match self.format {
+                XQuoFormat::Quote => true,
+                XQuoFormat::Env => matches!(self.env_style, XQuoEnvStyle::Shell),
+                XQuoFormat::Heredoc => false,
+            }
+        }
+
+        fn prepare_records<'a>(&self, lines: &'a Lines) -> Result<Vec<Cow<'a, [u8]>>> {
+            lines.records().map(|record| self.prepare(record)).collect()
+        }
+
+        // 入力の encoding の変換、Unicode の正規化、`-` で始まる行の保護を順番に行う.
+        fn prepare<'a>(&self, record: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+            let mut record = self.decode_input(record)?;
+            record = then(record, |v| Ok(normalize(v, &self.normalize)))?;
+            if let Some(policy) = &self.leading_dash {
+                record = then(record, |v| protect_leading_dash(v, policy))?;
+            }
+            Ok(record)
+        }
+
+        fn decode_input<'a>(&self, record: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+            match &self.input_encoding {
+                Some(encoding) => decode(record, &encoding.0, &self.unmappable),
+                None => Ok(Cow::Borrowed(record)),
+            }
+        }
+
+        fn colorize(&self, word: Vec<u8>) -> Vec<u8> {
+            if self.color {
+                colorize(&word, &self.dialect)
+            } else {
+                word
+            }
+        }
+
+        // escape の `'$'\xNN''` は bash の single quote の中でしか使えない.
+        fn check_unmappable_escape(&self, dialect: &XQuoDialect, shell_output: bool) -> Result<()> {
+            if self.output_encoding.is_none()
+                || !matches!(self.unmappable, XQuoUnmappable::Escape)
+                || (matches!(dialect, XQuoDialect::Bash) && shell_output)
+            {
+                return Ok(());
+            }
+            Err(anyhow!(
+                "--unmappable escape with --output-encoding can only be used for words quoted for bash"
+            ))
+        }
+
+        fn encode_output(&self, out: Vec<u8>) -> Result<Vec<u8>> {
+            match &self.output_encoding {
+                Some(encoding) => encode(out, &encoding.0, &self.unmappable),
+                None => Ok(out),
+            }
+        }
+    }
+
+    // 変換で新しい byte 列が作られたときだけ置き換える.
+    fn then<'a>(
+        record: Cow<'a, [u8]>,
+        f: impl FnOnce(&[u8]) -> Result<Cow<'_, [u8]>>,
+    ) -> Result<Cow<'a, [u8]>> {
+        let changed = match f(&record)? {
+            Cow::Borrowed(_) => None,
+            Cow::Owned(v) => Some(v),
+        };
+        Ok(changed.map_or(record, Cow::Owned))
+    }
+
+    fn print_examples(writer: impl std::io::Write) -> Result<()> {
+        let mut buf_writer = BufWriter::new(writer);
+        buf_writer.write_all(EXMAPLES_MESSAGE.to_string().as_bytes())?;
+        buf_writer.flush()?;
+        Ok(())
     }
 }
@@ -1,6 +1,16 @@
 use anyhow::Result;
-use clap::{Parser, ValueEnum};
-use xquo::cli::{XQuo, XQuoArgs, XQuoOutDelimiter};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::PathBuf;
+use xquo::cli::{
+    XQuo, XQuoArgs, XQuoAssign, XQuoAssignPrefix, XQuoAuditArgs, XQuoAuditFormat, XQuoColor,
+    XQuoDialect, XQuoEncoding, XQuoEnvStyle, XQuoExecArgs, XQuoFormat, XQuoInput, XQuoLeadingDash,
+    XQuoNormalize, XQuoOutDelimiter, XQuoScript, XQuoShell, XQuoUnmappable, XQuoVerifyShell,
+};
+
+mod config;
 
 #[cfg(feature = "jemalloc")]
 use tikv_jemallocator::Jemalloc;
@@ -15,6 +25,93 @@ pub enum OutDelimiter {
     Lf,
 }
 
+#[derive(Debug, Clone, ValueEnum)]
+pub enum Dialect {
+    Bash,
+    Fish,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum AuditFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum LeadingDash {
+    PrefixDotSlash,
+    Error,
+    Warn,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum Normalize {
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+    None,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum Unmappable {
+    Fail,
+    Replace,
+    /// With --output-encoding, only for words quoted for bash
+    Escape,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum Format {
+    Quote,
+    Env,
+    Heredoc,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum EnvStyle {
+    Shell,
+    Dotenv,
+    Docker,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum AssignPrefix {
+    Local,
+    Readonly,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum VerifyShell {
+    /// The shell of --dialect
+    Auto,
+    Bash,
+    Sh,
+    Zsh,
+    Fish,
+}
+
+fn dialect(dialect: Dialect) -> XQuoDialect {
+    match dialect {
+        Dialect::Bash => XQuoDialect::Bash,
+        Dialect::Fish => XQuoDialect::Fish,
+    }
+}
+
 fn workers_range(s: &str) -> Result<u8, String> {
     let n = s.to_string().parse::<u8>();
     match n {
@@ -43,18 +140,36 @@ fn bulk_range(s: &str) -> Result<usize, String> {
     }
 }
 
-const COMMAND_USAGE: &str = "xquo [OPTIONS] < /path/to/file";
+fn encoding_label(s: &str) -> Result<XQuoEncoding, String> {
+    XQuoEncoding::for_label(s).map_err(|e| e.to_string())
+}
+
+fn audit_max(s: &str) -> Result<(String, usize), String> {
+    let (kind, n) = s
+        .split_once('=')
+        .ok_or_else(|| "expected KIND=N".to_string())?;
+    let n = n.parse::<usize>().map_err(|e| e.to_string())?;
+    Ok((kind.to_string(), n))
+}
+
+const COMMAND_USAGE: &str = "xquo [OPTIONS] [FILE]...
+       xquo [OPTIONS] < /path/to/file
+       xquo [OPTIONS] <COMMAND>";
 
 /// Quote null splited lines for Bash command line
 #[derive(Parser)]
 #[clap(version, override_usage = COMMAND_USAGE)]
 struct Cli {
-    /// Disable to escape non-printable chars("\n", "\b")
-    #[clap(short, long)]
+    /// Disable to escape non-printable chars("\n", "\t", "\x1b", ...)
+    #[clap(short, long, global = true)]
     no_escape: bool,
 
+    /// The shell dialect to quote lines for. Use bash for zsh and ksh too.
+    #[clap(short, long, value_enum, default_value = "bash", global = true)]
+    dialect: Dialect,
+
     /// The delmiter char to split lines in output.
-    #[clap(short, long, value_enum, default_value = "lf")]
+    #[clap(short, long, value_enum, default_value = "lf", global = true)]
     out_delimiter: OutDelimiter,
 
     /// The number of workers.
@@ -62,18 +177,263 @@ struct Cli {
     workers: u8,
 
     /// The number of lines bundled in a single bulk.
-    #[clap(short, long, default_value = "100", value_parser=bulk_range)]
+    #[clap(short, long, default_value = "100", value_parser=bulk_range, global = true)]
     bulk_lines: usize,
 
     /// Input from tty.
-    #[clap(short = 't', long)]
+    #[clap(short = 't', long, global = true)]
     input_from_tty: bool,
+
+    /// Protect lines that begin with a dash from being parsed as options. Not used by split and convert.
+    #[clap(long, value_enum, value_name = "POLICY", global = true)]
+    safe_leading_dash: Option<LeadingDash>,
+
+    /// Normalize lines to the Unicode normal FORM before quoting. Invalid UTF-8 bytes are kept as is.
+    /// split and convert normalize each word of a command line.
+    #[clap(
+        long,
+        value_enum,
+        value_name = "FORM",
+        default_value = "none",
+        global = true
+    )]
+    normalize: Normalize,
+
+    /// The encoding of input lines, e.g. shift_jis, euc-jp or latin1. Lines are converted to UTF-8 before quoting.
+    #[clap(long, value_name = "ENCODING", value_parser = encoding_label, global = true)]
+    input_encoding: Option<XQuoEncoding>,
+
+    /// The encoding of the output. Quoted lines are converted from UTF-8.
+    #[clap(long, value_name = "ENCODING", value_parser = encoding_label, global = true)]
+    output_encoding: Option<XQuoEncoding>,
+
+    /// What to do with byte sequences that cannot be decoded or characters that cannot be encoded.
+    #[clap(
+        long,
+        value_enum,
+        value_name = "ACTION",
+        default_value = "fail",
+        global = true
+    )]
+    unmappable: Unmappable,
+
+    /// Evaluate each quoted line with SHELL and fail if it does not match the original line.
+    #[clap(
+        long,
+        value_enum,
+        value_name = "SHELL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "auto"
+    )]
+    verify: Option<VerifyShell>,
+
+    /// How to print each line. env treats lines as NAME=value and quotes only the value.
+    /// heredoc prints lines as they are in a here document and quotes only lines containing newlines.
+    #[clap(long, value_enum, default_value = "quote")]
+    format: Format,
+
+    /// The style of assignments printed by --format env and pid --environ.
+    /// shell prints export (bash) or set -gx (fish) lines.
+    #[clap(
+        long,
+        value_enum,
+        value_name = "STYLE",
+        default_value = "shell",
+        global = true
+    )]
+    env_style: EnvStyle,
+
+    /// Print each quoted line as an assignment to the variable NAME.
+    #[clap(
+        long,
+        value_name = "NAME",
+        group = "assign_to",
+        conflicts_with_all = ["format", "assign_indexed"]
+    )]
+    assign: Option<String>,
+
+    /// Print each quoted line as an assignment to an element of the array NAME.
+    #[clap(
+        long,
+        value_name = "NAME",
+        group = "assign_to",
+        conflicts_with = "format"
+    )]
+    assign_indexed: Option<String>,
+
+    /// Declare the variable of --assign or --assign-indexed with local or readonly.
+    #[clap(long, value_enum, value_name = "PREFIX", requires = "assign_to")]
+    assign_prefix: Option<AssignPrefix>,
+
+    /// Print a complete script with a shebang, set -euo pipefail and a header before the lines.
+    /// The lines must be statements: use it with --assign, --assign-indexed or --format env (shell style).
+    #[clap(long)]
+    script: bool,
+
+    /// Ask for confirmation before running the script printed by --script.
+    #[clap(long, requires = "script")]
+    confirm: bool,
+
+    /// Print each bulk as soon as it is quoted, without keeping the input order.
+    #[clap(short, long)]
+    unordered: bool,
+
+    /// Highlight quotes, escapes and invalid bytes in the output. auto colors only a terminal and respects NO_COLOR.
+    #[clap(
+        long,
+        value_enum,
+        value_name = "WHEN",
+        default_value = "auto",
+        global = true
+    )]
+    color: Color,
+
+    /// Use the settings of the profile NAME in the config file.
+    #[clap(long, value_name = "NAME", global = true)]
+    profile: Option<String>,
+
+    /// Read input from the files specified by NUL-terminated names in file F.
+    /// If F is - then read names from standard input.
+    #[clap(long, value_name = "F", global = true)]
+    files0_from: Option<PathBuf>,
+
+    /// Files to read lines from. With no FILE, or when FILE is -, read standard input.
+    /// A FILE named like a subcommand must be given as ./FILE or after --.
+    #[clap(value_name = "FILE")]
+    files: Vec<PathBuf>,
+
+    #[clap(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Quote each argument and print them as a single command line
+    QuoteArgs {
+        /// Arguments to quote.
+        #[clap(
+            value_name = "ARG",
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
+        args: Vec<OsString>,
+    },
+    /// Run a command for each line read from standard input or --files0-from
+    Exec {
+        /// Pass as many lines as possible to each command, like `find -exec {} +`.
+        #[clap(long)]
+        batch: bool,
+
+        /// The number of commands to run at a time.
+        #[clap(short = 'P', long, default_value = "1", value_parser=workers_range)]
+        max_procs: u8,
+
+        /// Print the quoted command lines instead of running them.
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Insert `--` before the first argument that receives lines.
+        #[clap(long)]
+        end_of_options: bool,
+
+        /// The command to run. `{}` is replaced by the line, or the line is appended if omitted.
+        #[clap(
+            value_name = "COMMAND",
+            required = true,
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
+        command: Vec<OsString>,
+    },
+    /// Report lines that contain dangerous characters for shell scripts
+    Audit {
+        /// The format of the report.
+        #[clap(short, long, value_enum, default_value = "text")]
+        format: AuditFormat,
+
+        /// The maximum number of lines allowed for KIND (default 0). Exit with an error when exceeded.
+        #[clap(long, value_name = "KIND=N", value_parser=audit_max)]
+        max: Vec<(String, usize)>,
+
+        /// Files to read lines from. With no FILE, or when FILE is -, read standard input.
+        #[clap(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Print each segment of a quoted word and warn about expansions
+    Explain {
+        /// The shell word to explain.
+        #[clap(value_name = "WORD")]
+        word: OsString,
+    },
+    /// Translate quoted command lines from one shell dialect to another
+    Convert {
+        /// The dialect the input is quoted for.
+        #[clap(long, value_enum, default_value = "bash")]
+        from: Dialect,
+
+        /// The dialect to quote the words for.
+        #[clap(long, value_enum)]
+        to: Dialect,
+
+        /// Files to read command lines from. With no FILE, or when FILE is -, read standard input.
+        #[clap(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Print the command line of a running process from /proc
+    Pid {
+        /// The process ID.
+        #[clap(value_name = "PID")]
+        pid: u32,
+
+        /// Print the environment of the process as export lines instead.
+        #[clap(long)]
+        environ: bool,
+    },
+    /// Split command lines into words and print them NUL-terminated
+    Split {
+        /// Files to read command lines from. With no FILE, or when FILE is -, read standard input.
+        #[clap(value_name = "FILE")]
+        files: Vec<PathBuf>,
+    },
+    /// Quote strings typed in the terminal interactively
+    Repl,
+    /// Print a shell completion script
+    Completions {
+        /// The shell to generate the script for.
+        #[clap(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Print the man page in roff format
+    Man,
+    /// Print a shell snippet defining key bindings to insert quoted words at the cursor
+    Init {
+        /// The shell to generate the snippet for.
+        #[clap(value_enum)]
+        shell: Shell,
+    },
+    /// Manage the defaults read from the config file and XQUO_* environment variables
+    Config {
+        #[clap(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print the effective settings and where they come from
+    Show,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Cli::parse();
+    let (matches, settings, config_path) =
+        config::get_matches(Cli::command(), std::env::args_os().collect(), &|name| {
+            std::env::var(name).ok()
+        })?;
+    let args = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
     let xquo = XQuo::new(XQuoArgs {
         no_escape: args.no_escape,
+        dialect: dialect(args.dialect.clone()),
         out_delimiter: match args.out_delimiter {
             OutDelimiter::Null => XQuoOutDelimiter::Null,
             _ => XQuoOutDelimiter::Lf,
@@ -81,8 +441,177 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         workers: args.workers,
         bulk_lines: args.bulk_lines,
         input_from_tty: args.input_from_tty,
+        unordered: args.unordered,
+        leading_dash: args.safe_leading_dash.map(|v| match v {
+            LeadingDash::PrefixDotSlash => XQuoLeadingDash::PrefixDotSlash,
+            LeadingDash::Error => XQuoLeadingDash::Error,
+            LeadingDash::Warn => XQuoLeadingDash::Warn,
+        }),
+        normalize: match args.normalize {
+            Normalize::Nfc => XQuoNormalize::Nfc,
+            Normalize::Nfd => XQuoNormalize::Nfd,
+            Normalize::Nfkc => XQuoNormalize::Nfkc,
+            Normalize::Nfkd => XQuoNormalize::Nfkd,
+            Normalize::None => XQuoNormalize::None,
+        },
+        input_encoding: args.input_encoding,
+        output_encoding: args.output_encoding,
+        unmappable: match args.unmappable {
+            Unmappable::Fail => XQuoUnmappable::Fail,
+            Unmappable::Replace => XQuoUnmappable::Replace,
+            Unmappable::Escape => XQuoUnmappable::Escape,
+        },
+        verify: args.verify.map(|v| match (v, &args.dialect) {
+            (VerifyShell::Auto, Dialect::Bash) => XQuoVerifyShell::Bash,
+            (VerifyShell::Auto, Dialect::Fish) => XQuoVerifyShell::Fish,
+            (VerifyShell::Bash, _) => XQuoVerifyShell::Bash,
+            (VerifyShell::Sh, _) => XQuoVerifyShell::Sh,
+            (VerifyShell::Zsh, _) => XQuoVerifyShell::Zsh,
+            (VerifyShell::Fish, _) => XQuoVerifyShell::Fish,
+        }),
+        format: match args.format {
+            Format::Quote => XQuoFormat::Quote,
+            Format::Env => XQuoFormat::Env,
+            Format::Heredoc => XQuoFormat::Heredoc,
+        },
+        env_style: match args.env_style {
+            EnvStyle::Shell => XQuoEnvStyle::Shell,
+            EnvStyle::Dotenv => XQuoEnvStyle::Dotenv,
+            EnvStyle::Docker => XQuoEnvStyle::Docker,
+        },
+        assign: match (&args.assign, &args.assign_indexed) {
+            (Some(name), _) | (None, Some(name)) => Some(XQuoAssign::new(
+                name,
+                args.assign_indexed.is_some(),
+                args.assign_prefix.map(|v| match v {
+                    AssignPrefix::Local => XQuoAssignPrefix::Local,
+                    AssignPrefix::Readonly => XQuoAssignPrefix::Readonly,
+                }),
+            )?),
+            (None, None) => None,
+        },
+        script: match args.script {
+            true => Some(XQuoScript {
+                args: std::env::args_os().skip(1).collect(),
+                confirm: args.confirm,
+            }),
+            false => None,
+        },
+        color: match args.color {
+            Color::Auto => XQuoColor::Auto,
+            Color::Always => XQuoColor::Always,
+            Color::Never => XQuoColor::Never,
+        },
     });
-    xquo.quote(std::io::stdin(), std::io::stdout())?;
+    let files = match &args.command {
+        Some(Commands::Audit { files, .. })
+        | Some(Commands::Split { files })
+        | Some(Commands::Convert { files, .. }) => files.clone(),
+        _ => args.files,
+    };
+    let inputs = match args.files0_from {
+        Some(_) if !files.is_empty() => Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "file operands cannot be combined with --files0-from",
+            )
+            .exit(),
+        Some(path) => XQuoInput::from_files0(&path)?,
+        None if files.is_empty() => vec![XQuoInput::Stdin],
+        None => files
+            .into_iter()
+            .map(|path| {
+                if path.as_os_str() == "-" {
+                    XQuoInput::Stdin
+                } else {
+                    XQuoInput::File(path)
+                }
+            })
+            .collect(),
+    };
+    let result = match args.command {
+        Some(Commands::QuoteArgs { args: quote_args }) => {
+            xquo.quote_args(&quote_args, std::io::stdout())
+        }
+        Some(Commands::Exec {
+            batch,
+            max_procs,
+            dry_run,
+            end_of_options,
+            command,
+        }) => xquo.exec(
+            &inputs,
+            XQuoExecArgs {
+                command,
+                batch,
+                max_procs,
+                dry_run,
+                end_of_options,
+            },
+            std::io::stdout(),
+        ),
+        Some(Commands::Audit { format, max, .. }) => xquo.audit(
+            &inputs,
+            XQuoAuditArgs {
+                format: match format {
+                    AuditFormat::Text => XQuoAuditFormat::Text,
+                    AuditFormat::Json => XQuoAuditFormat::Json,
+                },
+                max,
+            },
+            std::io::stdout(),
+        ),
+        Some(Commands::Config {
+            action: ConfigAction::Show,
+        }) => {
+            let show = config::show(&Cli::command(), &matches, &settings, config_path.as_deref());
+            std::io::stdout()
+                .write_all(show.as_bytes())
+                .map_err(anyhow::Error::from)
+        }
+        Some(Commands::Completions { shell }) => {
+            clap_complete::generate(
+                shell,
+                &mut config::command(Cli::command()),
+                "xquo",
+                &mut std::io::stdout(),
+            );
+            Ok(())
+        }
+        Some(Commands::Init { shell }) => xquo.init(
+            &match shell {
+                Shell::Bash => XQuoShell::Bash,
+                Shell::Zsh => XQuoShell::Zsh,
+                Shell::Fish => XQuoShell::Fish,
+            },
+            std::io::stdout(),
+        ),
+        Some(Commands::Explain { word }) => xquo.explain(&word, std::io::stdout()),
+        Some(Commands::Convert { from, to, .. }) => {
+            xquo.convert(&inputs, &dialect(from), &dialect(to), std::io::stdout())
+        }
+        Some(Commands::Pid { pid, environ }) => xquo.pid(pid, environ, std::io::stdout()),
+        Some(Commands::Split { .. }) => xquo.split(&inputs, std::io::stdout()),
+        Some(Commands::Repl) => xquo.repl(),
+        Some(Commands::Man) => clap_mangen::Man::new(config::command(Cli::command()))
+            .render(&mut std::io::stdout())
+            .map_err(anyhow::Error::from),
+        None => xquo.quote_inputs(&inputs, std::io::stdout()),
+    };
+    if let Err(err) = result {
+        if is_broken_pipe(&err) {
+            std::process::exit(1);
+        }
+        return Err(err.into());
+    }
 
     Ok(())
 }
+
+fn is_broken_pipe(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe)
+    })
+}
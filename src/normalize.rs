@@ -0,0 +1,86 @@
+use std::borrow::Cow;
+use unicode_normalization::{is_nfc, is_nfd, is_nfkc, is_nfkd, UnicodeNormalization};
+
+use crate::cli::XQuoNormalize;
+
+fn is_normalized(s: &str, form: &XQuoNormalize) -> bool {
+    match form {
+        XQuoNormalize::Nfc => is_nfc(s),
+        XQuoNormalize::Nfd => is_nfd(s),
+        XQuoNormalize::Nfkc => is_nfkc(s),
+        XQuoNormalize::Nfkd => is_nfkd(s),
+        XQuoNormalize::None => true,
+    }
+}
+
+fn normalize_str(s: &str, form: &XQuoNormalize) -> String {
+    match form {
+        XQuoNormalize::Nfc => s.nfc().collect(),
+        XQuoNormalize::Nfd => s.nfd().collect(),
+        XQuoNormalize::Nfkc => s.nfkc().collect(),
+        XQuoNormalize::Nfkd => s.nfkd().collect(),
+        XQuoNormalize::None => s.to_string(),
+    }
+}
+
+// UTF-8 として不正な byte 列はそのまま残す.
+pub fn is_normalized_record(record: &[u8], form: &XQuoNormalize) -> bool {
+    record
+        .utf8_chunks()
+        .all(|chunk| is_normalized(chunk.valid(), form))
+}
+
+// 行を指定された正規化形式に変換する. 既に正規化されていれば元の byte 列を返す.
+pub fn normalize<'a>(record: &'a [u8], form: &XQuoNormalize) -> Cow<'a, [u8]> {
+    if is_normalized_record(record, form) {
+        return Cow::Borrowed(record);
+    }
+    let mut ret = Vec::<u8>::new();
+    for chunk in record.utf8_chunks() {
+        ret.extend_from_slice(normalize_str(chunk.valid(), form).as_bytes());
+        ret.extend_from_slice(chunk.invalid());
+    }
+    Cow::Owned(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::XQuoNormalize;
+    use crate::normalize::{is_normalized_record, normalize};
+
+    // "ガイド" を合成済みの文字と、濁点を分解した文字で表したもの.
+    const NFC: &str = "\u{30ac}\u{30a4}\u{30c9}";
+    const NFD: &str = "\u{30ab}\u{3099}\u{30a4}\u{30c8}\u{3099}";
+
+    #[test]
+    fn normalize_record() {
+        assert_eq!(
+            normalize(NFD.as_bytes(), &XQuoNormalize::Nfc).as_ref(),
+            NFC.as_bytes()
+        );
+        assert_eq!(
+            normalize(NFC.as_bytes(), &XQuoNormalize::Nfd).as_ref(),
+            NFD.as_bytes()
+        );
+        assert_eq!(
+            normalize(NFD.as_bytes(), &XQuoNormalize::None).as_ref(),
+            NFD.as_bytes()
+        );
+        assert_eq!(
+            normalize("ｔｅｓｔ".as_bytes(), &XQuoNormalize::Nfkc).as_ref(),
+            b"test"
+        );
+        assert_eq!(
+            normalize(&[NFD.as_bytes(), b"\xff"].concat(), &XQuoNormalize::Nfc).as_ref(),
+            [NFC.as_bytes(), b"\xff"].concat()
+        );
+    }
+
+    #[test]
+    fn check_normalized() {
+        assert!(is_normalized_record(NFC.as_bytes(), &XQuoNormalize::Nfc));
+        assert!(!is_normalized_record(NFD.as_bytes(), &XQuoNormalize::Nfc));
+        assert!(is_normalized_record(NFD.as_bytes(), &XQuoNormalize::None));
+        assert!(is_normalized_record(b"test\xff", &XQuoNormalize::Nfd));
+    }
+}
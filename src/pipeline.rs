@@ -0,0 +1,160 @@
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use std::collections::BTreeMap;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+// 読み込んだ順番を seq で保持し、printer 側で並べ直す.
+struct Bulk<T> {
+    seq: usize,
+    lines: T,
+}
+struct Processed {
+    seq: usize,
+    out: Result<Vec<u8>>,
+}
+
+pub struct Pipeline {
+    pub workers: usize,
+    pub unordered: bool,
+}
+
+impl Pipeline {
+    // bulk を worker で process し、その結果を printer で書き出す.
+    pub fn run<T, F>(
+        &self,
+        bulks: impl Iterator<Item = Result<T>>,
+        writer: impl std::io::Write + Send,
+        process: F,
+    ) -> Result<()>
+    where
+        T: Send,
+        F: Fn(T) -> Result<Vec<u8>> + Sync,
+    {
+        let workers = self.workers.max(1);
+        // printer が書き出していない bulk の数を制限する(reorder buffer も含む).
+        let max_in_flight = workers * 4;
+        let (bulk_tx, bulk_rx) = bounded::<Bulk<T>>(workers);
+        let (out_tx, out_rx) = bounded::<Processed>(workers);
+        let (credit_tx, credit_rx) = bounded::<()>(max_in_flight);
+
+        thread::scope(|scope| {
+            let process = &process;
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    let bulk_rx = bulk_rx.clone();
+                    let out_tx = out_tx.clone();
+                    scope.spawn(move || work(process, bulk_rx, out_tx))
+                })
+                .collect();
+            drop(bulk_rx);
+            drop(out_tx);
+
+            let unordered = self.unordered;
+            let printer = scope.spawn(move || {
+                if unordered {
+                    print_as_received(writer, out_rx, credit_rx)
+                } else {
+                    print_in_order(writer, out_rx, credit_rx)
+                }
+            });
+
+            let mut read_result = Ok(());
+            for (seq, lines) in bulks.enumerate() {
+                let lines = match lines {
+                    Ok(lines) => lines,
+                    Err(err) => {
+                        read_result = Err(err);
+                        break;
+                    }
+                };
+                // printer か worker が止まっていれば送信に失敗するので、読み込みを打ち切る.
+                if credit_tx.send(()).is_err() || bulk_tx.send(Bulk { seq, lines }).is_err() {
+                    read_result = Err(anyhow!("could not send lines to quote thread"));
+                    break;
+                }
+            }
+            drop(bulk_tx);
+            drop(credit_tx);
+
+            let mut worker_result = Ok(());
+            for handle in handles {
+                let result = join(handle, "quote");
+                if worker_result.is_ok() {
+                    worker_result = result;
+                }
+            }
+            // worker の error も printer 経由で返される.
+            join(printer, "printer")?;
+            worker_result?;
+            read_result
+        })
+    }
+}
+
+fn work<T, F>(process: &F, bulk_rx: Receiver<Bulk<T>>, out_tx: Sender<Processed>) -> Result<()>
+where
+    F: Fn(T) -> Result<Vec<u8>>,
+{
+    for bulk in bulk_rx {
+        // panic も error として printer に渡し、パイプライン全体を止める.
+        let out = panic::catch_unwind(AssertUnwindSafe(|| process(bulk.lines)))
+            .unwrap_or_else(|err| Err(anyhow!("quote thread panicked: {}", panic_message(err))));
+        if out_tx.send(Processed { seq: bulk.seq, out }).is_err() {
+            // printer が先に終了している. error は printer 側から返される.
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn print_in_order(
+    writer: impl std::io::Write,
+    out_rx: Receiver<Processed>,
+    credit_rx: Receiver<()>,
+) -> Result<()> {
+    let mut buf_writer = BufWriter::new(writer);
+    let mut pending = BTreeMap::<usize, Vec<u8>>::new();
+    let mut next_seq = 0usize;
+    for processed in out_rx {
+        pending.insert(processed.seq, processed.out?);
+        while let Some(out) = pending.remove(&next_seq) {
+            buf_writer.write_all(&out)?;
+            // 書き出した分だけ reader が次の bulk を送れるようになる.
+            let _ = credit_rx.try_recv();
+            next_seq += 1;
+        }
+    }
+    buf_writer.flush()?;
+    Ok(())
+}
+
+fn print_as_received(
+    writer: impl std::io::Write,
+    out_rx: Receiver<Processed>,
+    credit_rx: Receiver<()>,
+) -> Result<()> {
+    let mut buf_writer = BufWriter::new(writer);
+    for processed in out_rx {
+        buf_writer.write_all(&processed.out?)?;
+        let _ = credit_rx.try_recv();
+    }
+    buf_writer.flush()?;
+    Ok(())
+}
+
+fn join(handle: thread::ScopedJoinHandle<'_, Result<()>>, name: &str) -> Result<()> {
+    handle
+        .join()
+        .map_err(|err| anyhow!("{} thread panicked: {}", name, panic_message(err)))
+        .and_then(|result| result)
+}
+
+fn panic_message(err: Box<dyn std::any::Any + Send>) -> String {
+    err.downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| err.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
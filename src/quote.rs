@@ -1,18 +1,42 @@
+use crate::cli::XQuoDialect;
+
 struct QuoteRplacePair {
     from: &'static str,
     to: &'static str,
 }
 
-pub trait DoQuote {
-    fn wrap_single_quote(&self, line: String) -> String {
+pub trait DoQuote: Send + Sync {
+    fn wrap_single_quote(&self, line: &str) -> String {
         line.replace('\'', "'\"'\"'")
     }
 
     fn replace(&self, line: String) -> String;
 
-    fn quote(&self, line: String) -> String {
+    fn quote(&self, line: &str) -> String {
         format!("'{}'", self.replace(self.wrap_single_quote(line)))
     }
+
+    // UTF-8 として不正な byte 列を quote する.
+    fn quote_invalid(&self, bytes: &[u8]) -> Vec<u8> {
+        let escaped: String = bytes.iter().map(|b| format!("\\x{:02x}", b)).collect();
+        format!("$'{}'", escaped).into_bytes()
+    }
+
+    fn quote_bytes(&self, line: &[u8]) -> Vec<u8> {
+        if let Ok(line) = std::str::from_utf8(line) {
+            return self.quote(line).into_bytes();
+        }
+        let mut ret = Vec::<u8>::new();
+        for chunk in line.utf8_chunks() {
+            if !chunk.valid().is_empty() {
+                ret.extend_from_slice(self.quote(chunk.valid()).as_bytes());
+            }
+            if !chunk.invalid().is_empty() {
+                ret.extend_from_slice(&self.quote_invalid(chunk.invalid()));
+            }
+        }
+        ret
+    }
 }
 
 pub struct QuoteBasic {}
@@ -21,6 +45,10 @@ impl DoQuote for QuoteBasic {
     fn replace(&self, line: String) -> String {
         line
     }
+
+    fn quote_invalid(&self, bytes: &[u8]) -> Vec<u8> {
+        [b"'", bytes, b"'"].concat()
+    }
 }
 
 pub struct QuotePrintable {}
@@ -41,48 +69,108 @@ impl DoQuote for QuotePrintable {
                 from: "\r",
                 to: "'$'\\r''",
             },
+            QuoteRplacePair {
+                from: "\t",
+                to: "'$'\\t''",
+            },
         ];
         let mut ret: String = line;
         for pair in TBL {
             ret = ret.replace(pair.from, pair.to);
         }
+        // 表にない制御文字も端末に影響しないように 16 進数で escape する.
+        if ret.contains(|c: char| c.is_ascii_control()) {
+            ret = ret
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_control() {
+                        format!("'$'\\x{:02x}''", c as u32)
+                    } else {
+                        c.to_string()
+                    }
+                })
+                .collect();
+        }
         ret
     }
 }
 
+// fish では single quote の中で `\` と `'` を escape でき、`$'...'` はない.
+pub struct QuoteFish {
+    pub printable: bool,
+}
+
+impl DoQuote for QuoteFish {
+    fn wrap_single_quote(&self, line: &str) -> String {
+        line.replace('\\', "\\\\").replace('\'', "\\'")
+    }
+
+    fn replace(&self, line: String) -> String {
+        if !self.printable || !line.contains(|c: char| c.is_ascii_control()) {
+            return line;
+        }
+        // 制御文字は quote の外で escape する.
+        line.chars()
+            .map(|c| match c {
+                '\u{8}' => "'\\b'".to_string(),
+                '\n' => "'\\n'".to_string(),
+                '\r' => "'\\r'".to_string(),
+                '\t' => "'\\t'".to_string(),
+                c if c.is_ascii_control() => format!("'\\x{:02x}'", c as u32),
+                c => c.to_string(),
+            })
+            .collect()
+    }
+
+    fn quote_invalid(&self, bytes: &[u8]) -> Vec<u8> {
+        let escaped: String = bytes.iter().map(|b| format!("\\x{:02x}", b)).collect();
+        escaped.into_bytes()
+    }
+}
+
+pub fn quoter(dialect: &XQuoDialect, no_escape: bool) -> Box<dyn DoQuote> {
+    match dialect {
+        XQuoDialect::Fish => Box::new(QuoteFish {
+            printable: !no_escape,
+        }),
+        XQuoDialect::Bash if !no_escape => Box::new(QuotePrintable {}),
+        XQuoDialect::Bash => Box::new(QuoteBasic {}),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::quote::{DoQuote, QuoteBasic, QuotePrintable};
+    use crate::quote::{DoQuote, QuoteBasic, QuoteFish, QuotePrintable};
 
     #[test]
     fn quote_line_by_basic() {
         let qb = QuoteBasic {};
 
-        let quoted = qb.quote("test".to_string());
+        let quoted = qb.quote("test");
         assert_eq!(quoted, "'test'");
 
-        let quoted = qb.quote("test test".to_string());
+        let quoted = qb.quote("test test");
         assert_eq!(quoted, "'test test'");
 
-        let quoted = qb.quote("test'test".to_string());
+        let quoted = qb.quote("test'test");
         assert_eq!(quoted, "'test'\"'\"'test'");
 
-        let quoted = qb.quote("test\ntest".to_string());
+        let quoted = qb.quote("test\ntest");
         assert_eq!(quoted, "'test\ntest'");
 
-        let quoted = qb.quote("test テスト".to_string());
+        let quoted = qb.quote("test テスト");
         assert_eq!(quoted, "'test テスト'");
 
-        let quoted = qb.quote("test'テスト".to_string());
+        let quoted = qb.quote("test'テスト");
         assert_eq!(quoted, "'test'\"'\"'テスト'");
 
-        let quoted = qb.quote("test''テスト".to_string());
+        let quoted = qb.quote("test''テスト");
         assert_eq!(quoted, "'test'\"'\"''\"'\"'テスト'");
 
-        let quoted = qb.quote("test'\nテスト".to_string());
+        let quoted = qb.quote("test'\nテスト");
         assert_eq!(quoted, "'test'\"'\"'\nテスト'");
 
-        let quoted = qb.quote("test'🦀テスト".to_string());
+        let quoted = qb.quote("test'🦀テスト");
         assert_eq!(quoted, "'test'\"'\"'🦀テスト'");
     }
 
@@ -90,28 +178,132 @@ mod tests {
     fn quote_line_by_printable() {
         let qb = QuotePrintable {};
 
-        let quoted = qb.quote("test\u{8}test".to_string());
+        let quoted = qb.quote("test\u{8}test");
         assert_eq!(quoted, "'test'$'\\b''test'");
 
-        let quoted = qb.quote("test test\u{8}".to_string());
+        let quoted = qb.quote("test test\u{8}");
         assert_eq!(quoted, "'test test'$'\\b'''");
 
-        let quoted = qb.quote("test\ntest".to_string());
+        let quoted = qb.quote("test\ntest");
         assert_eq!(quoted, "'test'$'\\n''test'");
 
-        let quoted = qb.quote("test test\n".to_string());
+        let quoted = qb.quote("test test\n");
         assert_eq!(quoted, "'test test'$'\\n'''");
 
-        let quoted = qb.quote("test\rtest".to_string());
+        let quoted = qb.quote("test\rtest");
         assert_eq!(quoted, "'test'$'\\r''test'");
 
-        let quoted = qb.quote("test test\r".to_string());
+        let quoted = qb.quote("test test\r");
         assert_eq!(quoted, "'test test'$'\\r'''");
 
-        let quoted = qb.quote("test\r\ntest".to_string());
+        let quoted = qb.quote("test\r\ntest");
         assert_eq!(quoted, "'test'$'\\r'''$'\\n''test'");
 
-        let quoted = qb.quote("test test\r\n".to_string());
+        let quoted = qb.quote("test test\r\n");
         assert_eq!(quoted, "'test test'$'\\r'''$'\\n'''");
+
+        let quoted = qb.quote("test\ttest");
+        assert_eq!(quoted, "'test'$'\\t''test'");
+
+        let quoted = qb.quote("test\x1b[0mtest\x7f");
+        assert_eq!(quoted, "'test'$'\\x1b''[0mtest'$'\\x7f'''");
+    }
+
+    #[test]
+    fn quote_bytes_by_basic() {
+        let qb = QuoteBasic {};
+
+        let quoted = qb.quote_bytes(b"test'test");
+        assert_eq!(quoted, b"'test'\"'\"'test'");
+
+        let quoted = qb.quote_bytes(b"test\xfftest");
+        assert_eq!(quoted, b"'test''\xff''test'");
+
+        let quoted = qb.quote_bytes(b"");
+        assert_eq!(quoted, b"''");
+    }
+
+    #[test]
+    fn quote_bytes_by_printable() {
+        let qb = QuotePrintable {};
+
+        let quoted = qb.quote_bytes(b"test\ntest");
+        assert_eq!(quoted, b"'test'$'\\n''test'");
+
+        let quoted = qb.quote_bytes(b"test\xfftest");
+        assert_eq!(quoted, b"'test'$'\\xff''test'");
+
+        let quoted = qb.quote_bytes(b"\xe3\x83test\xff");
+        assert_eq!(quoted, b"$'\\xe3\\x83''test'$'\\xff'");
+    }
+
+    #[test]
+    fn quote_line_by_fish() {
+        let qf = QuoteFish { printable: true };
+
+        let quoted = qf.quote("test test");
+        assert_eq!(quoted, "'test test'");
+
+        let quoted = qf.quote("test'test\\");
+        assert_eq!(quoted, "'test\\'test\\\\'");
+
+        let quoted = qf.quote("test\ntest\x1b");
+        assert_eq!(quoted, "'test'\\n'test'\\x1b''");
+
+        let quoted = qf.quote_bytes(b"test\xfftest");
+        assert_eq!(quoted, b"'test'\\xff'test'");
+
+        let qf = QuoteFish { printable: false };
+        let quoted = qf.quote("test\ntest");
+        assert_eq!(quoted, "'test\ntest'");
+    }
+
+    // quote した結果を unquote すると元に戻ることなどを、任意の byte 列で確認する.
+    mod properties {
+        use crate::quote::{DoQuote, QuoteBasic, QuoteFish, QuotePrintable};
+        use crate::unquote::{unquote, unquote_fish};
+        use proptest::prelude::*;
+
+        // 行には NUL が含まれないので、NUL 以外の byte 列と文字列を生成する.
+        fn records() -> impl Strategy<Value = Vec<u8>> {
+            prop_oneof![
+                proptest::collection::vec(1u8..=255, 0..64),
+                "[^\\x00]{0,32}".prop_map(|s| s.into_bytes()),
+                proptest::collection::vec(
+                    prop_oneof![
+                        Just(b'\''),
+                        Just(b'"'),
+                        Just(b'\\'),
+                        Just(b'\n'),
+                        Just(b'$'),
+                        1u8..=255
+                    ],
+                    0..32
+                ),
+            ]
+        }
+
+        proptest! {
+            #[test]
+            fn round_trip(record in records()) {
+                for q in [&QuoteBasic {} as &dyn DoQuote, &QuotePrintable {}] {
+                    let quoted = q.quote_bytes(&record);
+                    // unquote は閉じていない quote を error にする.
+                    prop_assert_eq!(unquote(&quoted).map_err(|e| e.to_string()), Ok(record.clone()));
+                }
+                for printable in [true, false] {
+                    let quoted = QuoteFish { printable }.quote_bytes(&record);
+                    prop_assert_eq!(unquote_fish(&quoted).map_err(|e| e.to_string()), Ok(record.clone()));
+                }
+            }
+
+            #[test]
+            fn printable_has_no_control_bytes(record in records()) {
+                let quoted = QuotePrintable {}.quote_bytes(&record);
+                prop_assert!(!quoted.iter().any(|b| b.is_ascii_control()), "{:?}", quoted);
+                let quoted = QuoteFish { printable: true }.quote_bytes(&record);
+                prop_assert!(!quoted.iter().any(|b| b.is_ascii_control()), "{:?}", quoted);
+            }
+        }
     }
 }
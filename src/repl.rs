@@ -0,0 +1,144 @@
+use anyhow::Result;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use crate::audit::display;
+use crate::cli::XQuoDialect;
+use crate::quote::quoter;
+use crate::unquote::{unquote, unquote_fish};
+
+const HELP: &str = "Type a string to print it quoted. Commands:
+  :dialect bash|fish  switch the shell dialect
+  :escape on|off      escape non-printable chars (off is the same as --no-escape)
+  :decode on|off      print the quoted word decoded back
+  :help               print this message
+  :quit               exit (or Ctrl-D)";
+
+pub struct Repl {
+    dialect: XQuoDialect,
+    escape: bool,
+    decode: bool,
+}
+
+impl Repl {
+    pub fn new(dialect: XQuoDialect, escape: bool) -> Repl {
+        Repl {
+            dialect,
+            escape,
+            decode: false,
+        }
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        let mut editor = DefaultEditor::new()?;
+        println!("{}", HELP);
+        loop {
+            match editor.readline(&self.prompt()) {
+                Ok(line) => {
+                    let _ = editor.add_history_entry(line.as_str());
+                    match self.eval(&line) {
+                        Some(out) => println!("{}", out),
+                        None => return Ok(()),
+                    }
+                }
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => return Ok(()),
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    fn prompt(&self) -> String {
+        format!(
+            "{}{}> ",
+            dialect_name(&self.dialect),
+            if self.escape { "" } else { " no-escape" }
+        )
+    }
+
+    // 入力された 1 行を処理して表示する文字列を返す. 終了する場合は None を返す.
+    pub fn eval(&mut self, line: &str) -> Option<String> {
+        match line.strip_prefix(':') {
+            Some(command) if !command.is_empty() => self.command(command),
+            _ => Some(self.quote(line)),
+        }
+    }
+
+    fn quote(&self, line: &str) -> String {
+        let q = quoter(&self.dialect, !self.escape);
+        let quoted = q.quote(line);
+        if !self.decode {
+            return quoted;
+        }
+        let decoded = match self.dialect {
+            XQuoDialect::Bash => unquote(quoted.as_bytes()),
+            XQuoDialect::Fish => unquote_fish(quoted.as_bytes()),
+        };
+        match decoded {
+            Ok(decoded) => format!("{}\n  decoded: \"{}\"", quoted, display(&decoded)),
+            Err(err) => format!("{}\n  could not decode: {}", quoted, err),
+        }
+    }
+
+    fn command(&mut self, command: &str) -> Option<String> {
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let arg = words.next();
+        let out = match (name, arg) {
+            ("quit" | "q" | "exit", _) => return None,
+            ("help" | "h", _) => HELP.to_string(),
+            ("dialect", Some("bash")) => {
+                self.dialect = XQuoDialect::Bash;
+                "dialect: bash".to_string()
+            }
+            ("dialect", Some("fish")) => {
+                self.dialect = XQuoDialect::Fish;
+                "dialect: fish".to_string()
+            }
+            ("dialect", None) => format!("dialect: {}", dialect_name(&self.dialect)),
+            ("escape" | "decode", Some(v @ ("on" | "off"))) => {
+                if name == "escape" {
+                    self.escape = v == "on";
+                } else {
+                    self.decode = v == "on";
+                }
+                format!("{}: {}", name, v)
+            }
+            _ => format!("unknown command: :{} (type :help)", command),
+        };
+        Some(out)
+    }
+}
+
+fn dialect_name(dialect: &XQuoDialect) -> &'static str {
+    match dialect {
+        XQuoDialect::Bash => "bash",
+        XQuoDialect::Fish => "fish",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::XQuoDialect;
+    use crate::repl::Repl;
+
+    #[test]
+    fn eval_lines() {
+        let mut repl = Repl::new(XQuoDialect::Bash, true);
+        assert_eq!(repl.eval("test'test").unwrap(), "'test'\"'\"'test'");
+        assert_eq!(repl.eval(":dialect fish").unwrap(), "dialect: fish");
+        assert_eq!(repl.eval("test'test").unwrap(), "'test\\'test'");
+        assert_eq!(repl.eval(":decode on").unwrap(), "decode: on");
+        assert_eq!(
+            repl.eval("a\tb").unwrap(),
+            "'a'\\t'b'\n  decoded: \"a\\tb\""
+        );
+        assert_eq!(repl.eval(":escape off").unwrap(), "escape: off");
+        assert_eq!(repl.prompt(), "fish no-escape> ");
+        assert!(repl
+            .eval(":unknown")
+            .unwrap()
+            .starts_with("unknown command"));
+        assert!(repl.eval(":quit").is_none());
+    }
+}
@@ -0,0 +1,70 @@
+use std::ffi::OsString;
+
+use crate::cli::XQuoDialect;
+use crate::quote::{DoQuote, QuotePrintable};
+
+// 出力の前に置く shebang、失敗したら止める設定、生成した時の情報を作る.
+pub fn header(dialect: &XQuoDialect, args: &[OsString], records: usize, confirm: bool) -> Vec<u8> {
+    // 改行を含む option でも comment が途切れないように escape する.
+    let q = QuotePrintable {};
+    let options: Vec<String> = args
+        .iter()
+        .map(|arg| q.quote(&arg.to_string_lossy()))
+        .collect();
+    let mut ret = Vec::<u8>::new();
+    ret.extend_from_slice(match dialect {
+        XQuoDialect::Bash => b"#!/usr/bin/env bash\n",
+        XQuoDialect::Fish => b"#!/usr/bin/env fish\n",
+    });
+    ret.extend_from_slice(
+        format!("# Generated by xquo {}\n", env!("CARGO_PKG_VERSION")).as_bytes(),
+    );
+    ret.extend_from_slice(b"# Options: ");
+    ret.extend_from_slice(options.join(" ").as_bytes());
+    ret.extend_from_slice(format!("\n# Records: {}\n", records).as_bytes());
+    // fish には errexit がないので、確認だけを行う.
+    if matches!(dialect, XQuoDialect::Bash) {
+        ret.extend_from_slice(b"set -euo pipefail\n");
+    }
+    if confirm {
+        let prompt = format!("'Run the script with {} record(s)? [y/N] '", records);
+        let check = match dialect {
+            XQuoDialect::Bash => format!(
+                "read -r -p {} answer </dev/tty\n[[ $answer == [yY]* ]] || exit 1\n",
+                prompt
+            ),
+            XQuoDialect::Fish => format!(
+                "read -P {} answer </dev/tty; or exit 1\nstring match -qi 'y*' -- $answer; or exit 1\n",
+                prompt
+            ),
+        };
+        ret.extend_from_slice(check.as_bytes());
+    }
+    ret.push(b'\n');
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use crate::cli::XQuoDialect;
+    use crate::script::header;
+
+    #[test]
+    fn script_header() {
+        let args: Vec<OsString> = vec!["--script".into(), "a\nb".into()];
+        let bash = String::from_utf8(header(&XQuoDialect::Bash, &args, 2, false)).unwrap();
+        assert_eq!(
+            bash,
+            format!(
+                "#!/usr/bin/env bash\n# Generated by xquo {}\n# Options: '--script' 'a'$'\\n''b'\n# Records: 2\nset -euo pipefail\n\n",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        let fish = String::from_utf8(header(&XQuoDialect::Fish, &args, 2, true)).unwrap();
+        assert!(fish.starts_with("#!/usr/bin/env fish\n"));
+        assert!(fish.contains("read -P 'Run the script with 2 record(s)? [y/N] ' answer"));
+        assert!(!fish.contains("set -euo pipefail"));
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::{anyhow, Result};
+
+use crate::cli::XQuoDialect;
+use crate::explain::{is_metachar, segment, show, Kind};
+use crate::unquote::{unquote, unquote_fish};
+
+// 長いものから順に比較する.
+const OPERATORS: &[&[u8]] = &[
+    b"<<<", b"<<-", b"&>>", b"&&", b"||", b";;", b";&", b"<<", b">>", b"<&", b">&", b"<>", b">|",
+    b"&>", b"|&", b"|", b"&", b";", b"(", b")", b"<", b">",
+];
+
+pub enum Token {
+    Word(Vec<u8>),
+    Operator(Vec<u8>),
+}
+
+// コマンドラインを shell と同じ規則で word と operator に分ける.
+// 実行時にしか値が決まらない展開を含む word は error にする.
+pub fn split(line: &[u8], dialect: &XQuoDialect) -> Result<Vec<Token>> {
+    let mut tokens = Vec::<Token>::new();
+    let mut i = 0;
+    while i < line.len() {
+        match line[i] {
+            b' ' | b'\t' | b'\n' => i += 1,
+            b'\\' if line.get(i + 1) == Some(&b'\n') => i += 2,
+            b'#' => break,
+            c if is_metachar(c) => {
+                let op = operator(&line[i..]);
+                tokens.push(Token::Operator(op.to_vec()));
+                i += op.len();
+            }
+            _ => {
+                let end = word_end(line, i, dialect);
+                let word = &line[i..end];
+                // `2>` のような fd の番号は redirection の一部にする.
+                if word.iter().all(u8::is_ascii_digit) && matches!(line.get(end), Some(b'<' | b'>'))
+                {
+                    let op = operator(&line[end..]);
+                    tokens.push(Token::Operator([word, op].concat()));
+                    i = end + op.len();
+                    continue;
+                }
+                tokens.push(Token::Word(resolve(word, i, dialect)?));
+                i = end;
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn operator(rest: &[u8]) -> &[u8] {
+    OPERATORS
+        .iter()
+        .find(|op| rest.starts_with(op))
+        .copied()
+        .unwrap_or(&rest[..1])
+}
+
+fn word_end(line: &[u8], start: usize, dialect: &XQuoDialect) -> usize {
+    let word = &line[start..];
+    let mut i = 0;
+    while i < word.len() {
+        let (end, kind) = segment(word, i, dialect);
+        if kind == Kind::Metachar {
+            break;
+        }
+        i = end;
+    }
+    start + i
+}
+
+fn resolve(word: &[u8], offset: usize, dialect: &XQuoDialect) -> Result<Vec<u8>> {
+    let mut i = 0;
+    while i < word.len() {
+        let (end, kind) = segment(word, i, dialect);
+        if matches!(
+            kind,
+            Kind::Expansion | Kind::Brace | Kind::Glob | Kind::Tilde
+        ) {
+            return Err(anyhow!(
+                "could not resolve {} in {} at byte {}",
+                show(&word[i..end]),
+                show(word),
+                offset + i
+            ));
+        }
+        i = end;
+    }
+    let resolved = match dialect {
+        XQuoDialect::Bash => unquote(word),
+        XQuoDialect::Fish => unquote_fish(word),
+    };
+    resolved.map_err(|e| anyhow!("could not resolve {} at byte {}: {}", show(word), offset, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::XQuoDialect;
+    use crate::split::{split, Token};
+
+    fn tokens(line: &[u8]) -> Vec<String> {
+        split(line, &XQuoDialect::Bash)
+            .unwrap()
+            .into_iter()
+            .map(|v| match v {
+                Token::Word(v) => String::from_utf8(v).unwrap(),
+                Token::Operator(v) => format!("<{}>", String::from_utf8(v).unwrap()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_command_line() {
+        assert_eq!(
+            tokens(b"ls -l 'a b'\"'\"c\\ d $'\\t' # comment"),
+            ["ls", "-l", "a b'c d", "\t"]
+        );
+        assert_eq!(
+            tokens(b"cat a|grep -v b&&echo ''>out 2>&1;x"),
+            [
+                "cat", "a", "<|>", "grep", "-v", "b", "<&&>", "echo", "", "<>>", "out", "<2>&>",
+                "1", "<;>", "x"
+            ]
+        );
+        assert_eq!(tokens(b"a\\\nb\nc"), ["ab", "c"]);
+    }
+
+    #[test]
+    fn fail_on_expansion() {
+        assert!(split(b"echo $HOME", &XQuoDialect::Bash).is_err());
+        assert!(split(b"ls *.txt", &XQuoDialect::Bash).is_err());
+        assert!(split(b"echo \"$(ls)\"", &XQuoDialect::Bash).is_err());
+        assert!(split(b"echo 'a", &XQuoDialect::Bash).is_err());
+        assert!(split(b"echo {a,b}", &XQuoDialect::Bash).is_err());
+        assert!(split(b"echo x{1..3}", &XQuoDialect::Bash).is_err());
+        assert!(split(b"echo {a} '{a,b}'", &XQuoDialect::Bash).is_ok());
+        assert!(split(b"echo '$HOME' \"\\$x\"", &XQuoDialect::Bash).is_ok());
+    }
+}
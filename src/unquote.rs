@@ -0,0 +1,302 @@
+use anyhow::{anyhow, Result};
+
+// Bash の 1 つの word を quote される前の byte 列に戻す.
+// 展開やメタ文字を含む word は、元の値が決まらないので error にする.
+pub fn unquote(word: &[u8]) -> Result<Vec<u8>> {
+    let mut ret = Vec::<u8>::new();
+    let mut i = 0;
+    while i < word.len() {
+        match word[i] {
+            b'\'' => {
+                let end = find(word, i + 1, b'\'')
+                    .ok_or_else(|| anyhow!("unterminated single quote at byte {}", i))?;
+                ret.extend_from_slice(&word[i + 1..end]);
+                i = end + 1;
+            }
+            b'$' if word.get(i + 1) == Some(&b'\'') => {
+                i = ansi_c(word, i + 2, &mut ret)
+                    .ok_or_else(|| anyhow!("unterminated $' quote at byte {}", i))?;
+            }
+            b'"' => {
+                i = double_quote(word, i + 1, &mut ret)?;
+            }
+            b'\\' => match word.get(i + 1) {
+                Some(b'\n') => i += 2,
+                Some(c) => {
+                    ret.push(*c);
+                    i += 2;
+                }
+                None => return Err(anyhow!("trailing backslash at byte {}", i)),
+            },
+            b'$' | b'`' => return Err(anyhow!("unquoted expansion at byte {}", i)),
+            b'*' | b'?' | b'[' => return Err(anyhow!("unquoted glob at byte {}", i)),
+            b'~' | b'#' if i == 0 => {
+                return Err(anyhow!("unquoted {:?} at byte {}", word[i] as char, i))
+            }
+            c if is_metachar(c) => {
+                return Err(anyhow!(
+                    "unquoted metacharacter {:?} at byte {}",
+                    c as char,
+                    i
+                ))
+            }
+            c => {
+                ret.push(c);
+                i += 1;
+            }
+        }
+    }
+    Ok(ret)
+}
+
+// fish の 1 つの word を quote される前の byte 列に戻す.
+pub fn unquote_fish(word: &[u8]) -> Result<Vec<u8>> {
+    let mut ret = Vec::<u8>::new();
+    let mut i = 0;
+    while i < word.len() {
+        match word[i] {
+            b'\'' => i = fish_single_quote(word, i + 1, &mut ret)?,
+            b'"' => i = double_quote(word, i + 1, &mut ret)?,
+            b'\\' => match word.get(i + 1) {
+                Some(b'\n') => i += 2,
+                Some(b'x' | b'X') => {
+                    let (v, n) = digits(&word[i + 2..], 16, 2);
+                    if n == 0 {
+                        return Err(anyhow!("invalid escape at byte {}", i));
+                    }
+                    ret.push(v as u8);
+                    i += 2 + n;
+                }
+                Some(
+                    b'a'
+                    | b'b'
+                    | b'e'
+                    | b'f'
+                    | b'n'
+                    | b'r'
+                    | b't'
+                    | b'v'
+                    | b'0'..=b'7'
+                    | b'u'
+                    | b'U',
+                ) => i = escape(word, i, &mut ret),
+                // それ以外の文字は backslash を取り除いた文字になる.
+                Some(c) => {
+                    ret.push(*c);
+                    i += 2;
+                }
+                None => return Err(anyhow!("trailing backslash at byte {}", i)),
+            },
+            b'$' | b'(' => return Err(anyhow!("unquoted expansion at byte {}", i)),
+            b'*' | b'?' | b'{' => return Err(anyhow!("unquoted glob or brace at byte {}", i)),
+            b'~' | b'#' if i == 0 => {
+                return Err(anyhow!("unquoted {:?} at byte {}", word[i] as char, i))
+            }
+            c if is_metachar(c) => {
+                return Err(anyhow!(
+                    "unquoted metacharacter {:?} at byte {}",
+                    c as char,
+                    i
+                ))
+            }
+            c => {
+                ret.push(c);
+                i += 1;
+            }
+        }
+    }
+    Ok(ret)
+}
+
+// fish の single quote の中では `\'` と `\\` だけが escape になる.
+fn fish_single_quote(word: &[u8], start: usize, ret: &mut Vec<u8>) -> Result<usize> {
+    let mut i = start;
+    while i < word.len() {
+        match word[i] {
+            b'\'' => return Ok(i + 1),
+            b'\\' if matches!(word.get(i + 1), Some(b'\'' | b'\\')) => {
+                ret.push(word[i + 1]);
+                i += 2;
+            }
+            c => {
+                ret.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err(anyhow!("unterminated single quote at byte {}", start - 1))
+}
+
+fn is_metachar(c: u8) -> bool {
+    matches!(
+        c,
+        b' ' | b'\t' | b'\n' | b'|' | b'&' | b';' | b'(' | b')' | b'<' | b'>'
+    )
+}
+
+fn find(word: &[u8], start: usize, c: u8) -> Option<usize> {
+    word[start..]
+        .iter()
+        .position(|v| *v == c)
+        .map(|pos| pos + start)
+}
+
+// `"` の中では `$` `` ` `` `"` `\` 改行 の前の backslash だけが特別な意味を持つ.
+fn double_quote(word: &[u8], start: usize, ret: &mut Vec<u8>) -> Result<usize> {
+    let mut i = start;
+    while i < word.len() {
+        match word[i] {
+            b'"' => return Ok(i + 1),
+            b'\\' => match word.get(i + 1) {
+                Some(b'\n') => i += 2,
+                Some(c @ (b'$' | b'`' | b'"' | b'\\')) => {
+                    ret.push(*c);
+                    i += 2;
+                }
+                _ => {
+                    ret.push(b'\\');
+                    i += 1;
+                }
+            },
+            b'$' | b'`' => return Err(anyhow!("expansion in double quote at byte {}", i)),
+            c => {
+                ret.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err(anyhow!("unterminated double quote at byte {}", start - 1))
+}
+
+// `$'...'` の中の escape を展開し、閉じ quote の次の位置を返す.
+fn ansi_c(word: &[u8], start: usize, ret: &mut Vec<u8>) -> Option<usize> {
+    let mut i = start;
+    loop {
+        match *word.get(i)? {
+            b'\'' => return Some(i + 1),
+            b'\\' => {
+                word.get(i + 1)?;
+                i = escape(word, i, ret);
+            }
+            c => {
+                ret.push(c);
+                i += 1;
+            }
+        }
+    }
+}
+
+// word[i] の backslash から始まる escape を 1 つ展開し、次の位置を返す.
+fn escape(word: &[u8], i: usize, ret: &mut Vec<u8>) -> usize {
+    let c = word[i + 1];
+    let i = i + 2;
+    match c {
+        b'a' => ret.push(0x07),
+        b'b' => ret.push(0x08),
+        b'e' | b'E' => ret.push(0x1b),
+        b'f' => ret.push(0x0c),
+        b'n' => ret.push(b'\n'),
+        b'r' => ret.push(b'\r'),
+        b't' => ret.push(b'\t'),
+        b'v' => ret.push(0x0b),
+        b'\\' | b'\'' | b'"' | b'?' => ret.push(c),
+        b'x' => {
+            let (v, n) = digits(&word[i..], 16, 2);
+            if n == 0 {
+                ret.extend_from_slice(b"\\x");
+            } else {
+                ret.push(v as u8);
+            }
+            return i + n;
+        }
+        b'0'..=b'7' => {
+            let (v, n) = digits(&word[i - 1..], 8, 3);
+            ret.push(v as u8);
+            return i + n - 1;
+        }
+        b'u' | b'U' => {
+            let max = if c == b'u' { 4 } else { 8 };
+            let (v, n) = digits(&word[i..], 16, max);
+            match char::from_u32(v) {
+                Some(ch) if n > 0 => {
+                    let mut buf = [0; 4];
+                    ret.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                }
+                _ => {
+                    ret.extend_from_slice(&[b'\\', c]);
+                    ret.extend_from_slice(&word[i..i + n]);
+                }
+            }
+            return i + n;
+        }
+        c => ret.extend_from_slice(&[b'\\', c]),
+    }
+    i
+}
+
+fn digits(s: &[u8], radix: u32, max: usize) -> (u32, usize) {
+    let mut v = 0;
+    let mut n = 0;
+    for c in s.iter().take(max) {
+        match (*c as char).to_digit(radix) {
+            Some(d) => v = v * radix + d,
+            None => break,
+        }
+        n += 1;
+    }
+    (v, n)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::unquote::{unquote, unquote_fish};
+
+    #[test]
+    fn unquote_word() {
+        assert_eq!(unquote(b"test").unwrap(), b"test");
+        assert_eq!(unquote(b"'test test'").unwrap(), b"test test");
+        assert_eq!(unquote(b"'test'\"'\"'test'").unwrap(), b"test'test");
+        assert_eq!(unquote(b"test\\ test").unwrap(), b"test test");
+        assert_eq!(unquote(b"\"test \\$ \\a\"").unwrap(), b"test $ \\a");
+        assert_eq!(unquote(b"''").unwrap(), b"");
+    }
+
+    #[test]
+    fn unquote_ansi_c() {
+        assert_eq!(unquote(b"$'\\n\\t\\b\\r\\e'").unwrap(), b"\n\t\x08\r\x1b");
+        assert_eq!(unquote(b"$'\\xff\\x1'").unwrap(), b"\xff\x01");
+        assert_eq!(unquote(b"$'\\101\\0'").unwrap(), b"A\0");
+        assert_eq!(unquote(b"$'\\'\\\\'").unwrap(), b"'\\");
+        assert_eq!(unquote(b"$'\\u30c6'").unwrap(), "テ".as_bytes());
+        assert_eq!(unquote(b"$'\\q'").unwrap(), b"\\q");
+    }
+
+    #[test]
+    fn fail_on_unbalanced_or_unquoted() {
+        assert!(unquote(b"'test").is_err());
+        assert!(unquote(b"$'test").is_err());
+        assert!(unquote(b"\"test").is_err());
+        assert!(unquote(b"test test").is_err());
+        assert!(unquote(b"$HOME").is_err());
+        assert!(unquote(b"\"$HOME\"").is_err());
+        assert!(unquote(b"*.txt").is_err());
+        assert!(unquote(b"~/test").is_err());
+        assert!(unquote(b"test;").is_err());
+    }
+
+    #[test]
+    fn unquote_fish_word() {
+        assert_eq!(unquote_fish(b"'test test'").unwrap(), b"test test");
+        assert_eq!(
+            unquote_fish(b"'test\\'test\\\\\\a'").unwrap(),
+            b"test'test\\\\a"
+        );
+        assert_eq!(
+            unquote_fish(b"'test'\\n'test'\\x1b").unwrap(),
+            b"test\ntest\x1b"
+        );
+        assert_eq!(unquote_fish(b"\\xff\\ \\$").unwrap(), b"\xff $");
+        assert!(unquote_fish(b"'test").is_err());
+        assert!(unquote_fish(b"(ls)").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::prelude::*;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::audit::display;
+use crate::cli::XQuoVerifyShell;
+
+fn shell_name(shell: &XQuoVerifyShell) -> &'static str {
+    match shell {
+        XQuoVerifyShell::Bash => "bash",
+        XQuoVerifyShell::Sh => "sh",
+        XQuoVerifyShell::Zsh => "zsh",
+        XQuoVerifyShell::Fish => "fish",
+    }
+}
+
+// quote した word を `printf '%s\0'` で出力するスクリプト. fish では `string join0` を使う.
+fn script(shell: &XQuoVerifyShell, words: &[&[u8]]) -> Vec<u8> {
+    let print: &[u8] = match shell {
+        XQuoVerifyShell::Fish => b"string join0 -- ",
+        _ => b"printf '%s\\0' ",
+    };
+    let mut ret = Vec::<u8>::new();
+    for word in words {
+        ret.extend_from_slice(print);
+        ret.extend_from_slice(word);
+        ret.push(b'\n');
+    }
+    ret
+}
+
+// bulk 単位で shell を起動して word を評価し、元の行と一致するか確認する.
+pub fn verify(shell: &XQuoVerifyShell, records: &[&[u8]], words: &[&[u8]]) -> Result<()> {
+    let name = shell_name(shell);
+    let mut child = Command::new(name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("could not run {} to verify", name))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let script = script(shell, words);
+    // 出力を読みながら書き込まないと、pipe が詰まって止まることがある.
+    let output = thread::scope(|scope| {
+        let writer = scope.spawn(move || stdin.write_all(&script));
+        let output = child.wait_with_output();
+        let _ = writer.join();
+        output
+    })
+    .with_context(|| format!("could not run {} to verify", name))?;
+
+    // NUL で終わっていない出力は評価が途中で止まったものとして扱う.
+    let mut evaluated = output
+        .stdout
+        .split_inclusive(|b| *b == b'\0')
+        .filter_map(|v| v.strip_suffix(b"\0"));
+    for (record, word) in records.iter().zip(words) {
+        match evaluated.next() {
+            Some(v) if v == *record => {}
+            Some(v) => {
+                return Err(anyhow!(
+                    "verification failed: \"{}\" was evaluated as \"{}\" by {} (quoted: \"{}\")",
+                    display(record),
+                    display(v),
+                    name,
+                    display(word)
+                ))
+            }
+            None => {
+                return Err(anyhow!(
+                    "verification failed: \"{}\" was not evaluated by {} (quoted: \"{}\"): {}",
+                    display(record),
+                    name,
+                    display(word),
+                    String::from_utf8_lossy(&output.stderr).trim_end()
+                ))
+            }
+        }
+    }
+    if !output.status.success() {
+        return Err(anyhow!(
+            "verification failed: {} exited with {}: {}",
+            name,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::cli::XQuoVerifyShell;
+    use crate::quote::{DoQuote, QuoteBasic, QuotePrintable};
+    use crate::verify::{script, verify};
+
+    #[test]
+    fn verify_quoted_words() {
+        let records: Vec<&[u8]> = vec![
+            b"test",
+            b"test test",
+            b"'test' \"test\"",
+            b"$HOME `ls` *",
+            b"test\ntest\r\x08",
+            "テスト🦀".as_bytes(),
+            b"test\xfftest",
+            b"",
+        ];
+        for q in [&QuotePrintable {} as &dyn DoQuote, &QuoteBasic {}] {
+            let words: Vec<Vec<u8>> = records.iter().map(|r| q.quote_bytes(r)).collect();
+            let words: Vec<&[u8]> = words.iter().map(|w| w.as_slice()).collect();
+            verify(&XQuoVerifyShell::Bash, &records, &words).unwrap();
+        }
+    }
+
+    #[test]
+    fn fail_on_mismatch() {
+        let err = verify(&XQuoVerifyShell::Bash, &[b"a b"], &[b"a b"]).unwrap_err();
+        assert!(err.to_string().contains("was evaluated as \"a\""));
+        let err = verify(&XQuoVerifyShell::Bash, &[b"a"], &[b"'a"]).unwrap_err();
+        assert!(err.to_string().contains("was not evaluated"));
+    }
+
+    #[test]
+    fn script_for_fish() {
+        assert_eq!(
+            script(&XQuoVerifyShell::Fish, &[b"'it\\'s'"]),
+            b"string join0 -- 'it\\'s'\n"
+        );
+    }
+}
@@ -1,5 +1,7 @@
+use std::io::{Seek, SeekFrom, Write};
 use std::str::from_utf8;
 
+use assert_cmd::prelude::*;
 use assert_cmd::Command;
 use predicates::prelude::*;
 
@@ -62,11 +64,26 @@ fn joined_by_null() -> Result<(), Box<dyn std::error::Error>> {
     ];
     let ex = ex_lines.map(|v| format!("'{}'", v)).join("\0") + "\0";
 
-    cmd.write_stdin(input_lines).args(&["-o", "null"]);
+    cmd.write_stdin(input_lines).args(["-o", "null"]);
     cmd.assert().success().stdout(predicate::eq(ex.as_bytes()));
     Ok(())
 }
 
+#[test]
+fn escape_tabs_and_control_chars() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a\tb\0c\x1bd\0e\x7f\0");
+    cmd.assert()
+        .success()
+        .stdout("'a'$'\\t''b'\n'c'$'\\x1b''d'\n'e'$'\\x7f'''\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a\tb\0");
+    cmd.args(["--no-escape"]);
+    cmd.assert().success().stdout("'a\tb'\n");
+    Ok(())
+}
+
 #[test]
 fn disable_escape_chars() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin("xquo")?;
@@ -93,7 +110,7 @@ fn disable_escape_chars() -> Result<(), Box<dyn std::error::Error>> {
     ];
     let ex = ex_lines.map(|v| format!("'{}'", v)).join("\n") + "\n";
 
-    cmd.write_stdin(input_lines).args(&["-n"]);
+    cmd.write_stdin(input_lines).args(["-n"]);
     cmd.assert().success().stdout(predicate::eq(ex.as_bytes()));
     Ok(())
 }
@@ -124,7 +141,7 @@ fn disable_escape_chars_and_joined_by_null() -> Result<(), Box<dyn std::error::E
     ];
     let ex = ex_lines.map(|v| format!("'{}'", v)).join("\0") + "\0";
 
-    cmd.write_stdin(input_lines).args(&["-n", "-o", "null"]);
+    cmd.write_stdin(input_lines).args(["-n", "-o", "null"]);
     cmd.assert().success().stdout(predicate::eq(ex.as_bytes()));
     Ok(())
 }
@@ -159,6 +176,1015 @@ fn use_large_data_that_is_shuffle_lines_in_parallel_mode() -> Result<(), Box<dyn
     Ok(())
 }
 
+#[test]
+fn keep_order_with_many_workers_and_small_bulks() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    let mut lines = Vec::<String>::new();
+    for i in 0..5000 {
+        lines.push(format!("{:04}", i));
+    }
+    let input_lines = lines.join("\0");
+    let ex = lines
+        .iter()
+        .map(|v| format!("'{}'", v))
+        .collect::<Vec<_>>()
+        .join("\n")
+        + "\n";
+
+    cmd.write_stdin(input_lines).args(["-w", "8", "-b", "1"]);
+    cmd.assert().success().stdout(predicate::eq(ex.as_bytes()));
+    Ok(())
+}
+
+#[test]
+fn fail_on_invalid_utf8_in_parallel_mode() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    let mut input = Vec::<u8>::new();
+    for i in 0..1000 {
+        input.extend_from_slice(format!("{:04}\0", i).as_bytes());
+    }
+    input.extend_from_slice(b"test\xfftest\0");
+    for i in 0..1000 {
+        input.extend_from_slice(format!("{:04}\0", i).as_bytes());
+    }
+
+    cmd.write_stdin(input).args(["-w", "4", "-b", "10"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("could not decode line as UTF-8"));
+    Ok(())
+}
+
+#[test]
+fn unordered_output_is_permutation_of_ordered_output() -> Result<(), Box<dyn std::error::Error>> {
+    let mut lines = Vec::<String>::new();
+    for i in 0..10000 {
+        lines.push(format!("{:04}'{}", i, i % 7));
+    }
+    let input_lines = lines.join("\0");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin(input_lines.clone())
+        .args(["-w", "4", "-b", "10"]);
+    let ordered = cmd.assert().success().get_output().stdout.clone();
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin(input_lines)
+        .args(["-w", "4", "-b", "10", "-u"]);
+    let unordered = cmd.assert().success().get_output().stdout.clone();
+
+    let mut ordered_lines: Vec<&str> = from_utf8(&ordered)?.split('\n').collect();
+    let mut unordered_lines: Vec<&str> = from_utf8(&unordered)?.split('\n').collect();
+    ordered_lines.sort_unstable();
+    unordered_lines.sort_unstable();
+    assert_eq!(ordered_lines, unordered_lines);
+    Ok(())
+}
+
+#[test]
+fn quote_lines_from_regular_file() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    let mut lines = Vec::<String>::new();
+    for i in 0..10000 {
+        lines.push(format!("{:04}'\n", i));
+    }
+    lines.push("".to_string());
+    lines.push("テスト🦀".to_string());
+    file.write_all(lines.join("\0").as_bytes())?;
+    file.flush()?;
+    let ex = lines
+        .iter()
+        .map(|v| format!("'{}'", v.replace('\'', "'\"'\"'").replace('\n', "'$'\\n''")))
+        .collect::<Vec<_>>()
+        .join("\n")
+        + "\n";
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.pipe_stdin(file.path())?.args(["-w", "4", "-b", "3"]);
+    cmd.assert().success().stdout(predicate::eq(ex.as_bytes()));
+    Ok(())
+}
+
+#[test]
+fn quote_lines_from_current_position_of_regular_file() -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    file.write_all("skip\0test\0test test\0".as_bytes())?;
+    file.flush()?;
+    let mut stdin = file.reopen()?;
+    stdin.seek(SeekFrom::Start(5))?;
+
+    // assert_cmd::Command は stdin を差し替えるので std の Command を使う.
+    let mut cmd = std::process::Command::new(assert_cmd::cargo::cargo_bin("xquo"));
+    cmd.stdin(stdin);
+    cmd.assert()
+        .success()
+        .stdout(predicate::eq("'test'\n'test test'\n".as_bytes()));
+    Ok(())
+}
+
+fn temp_file_with(content: &[u8]) -> Result<tempfile::NamedTempFile, Box<dyn std::error::Error>> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    file.write_all(content)?;
+    file.flush()?;
+    Ok(file)
+}
+
+#[test]
+fn quote_lines_from_files_in_order() -> Result<(), Box<dyn std::error::Error>> {
+    let file1 = temp_file_with("test\0test test\0".as_bytes())?;
+    let file2 = temp_file_with("テスト🦀\0テスト'テスト".as_bytes())?;
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("stdin\0")
+        .arg(file1.path())
+        .arg("-")
+        .arg(file2.path())
+        .args(["-w", "2", "-b", "1"]);
+    cmd.assert().success().stdout(predicate::eq(
+        "'test'\n'test test'\n'stdin'\n'テスト🦀'\n'テスト'\"'\"'テスト'\n".as_bytes(),
+    ));
+    Ok(())
+}
+
+#[test]
+fn quote_lines_from_file_named_like_subcommand() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    std::fs::write(dir.path().join("man"), "test test\0")?;
+
+    for args in [["./man"].as_slice(), &["--", "man"]] {
+        let mut cmd = Command::cargo_bin("xquo")?;
+        cmd.current_dir(dir.path()).args(args);
+        cmd.assert().success().stdout("'test test'\n");
+    }
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.current_dir(dir.path()).args(["man"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(".TH xquo"));
+    Ok(())
+}
+
+#[test]
+fn quote_lines_from_files0_from() -> Result<(), Box<dyn std::error::Error>> {
+    let file1 = temp_file_with("test\0".as_bytes())?;
+    let file2 = temp_file_with("test test\0".as_bytes())?;
+    let mut names = Vec::<u8>::new();
+    for file in [&file2, &file1] {
+        names.extend_from_slice(file.path().to_str().unwrap().as_bytes());
+        names.push(0);
+    }
+    let list = temp_file_with(&names)?;
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.arg("--files0-from").arg(list.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::eq("'test test'\n'test'\n".as_bytes()));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin(names).args(["--files0-from", "-"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::eq("'test test'\n'test'\n".as_bytes()));
+    Ok(())
+}
+
+#[test]
+fn fail_on_file_does_not_exist() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.arg("/path/to/file/does/not/exist");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "could not open /path/to/file/does/not/exist",
+    ));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("-\0").args(["--files0-from", "-"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("no file name of '-' allowed"));
+    Ok(())
+}
+
+#[test]
+fn quote_args() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.args(["quote-args", "test", "test'test", "テスト🦀\n", "-rf"]);
+    cmd.assert().success().stdout(predicate::eq(
+        "'test' 'test'\"'\"'test' 'テスト🦀'$'\\n''' '-rf'\n".as_bytes(),
+    ));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.args(["-n", "quote-args", "--", "-n", "test\ntest"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::eq("'-n' 'test\ntest'\n".as_bytes()));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn quote_args_with_invalid_utf8() -> Result<(), Box<dyn std::error::Error>> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.arg("quote-args")
+        .arg(OsStr::from_bytes(b"test\xfftest"));
+    cmd.assert()
+        .success()
+        .stdout(predicate::eq("'test'$'\\xff''test'\n".as_bytes()));
+    Ok(())
+}
+
+#[test]
+fn exec_dry_run() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("test\0test'test\0テスト\n🦀\0").args([
+        "exec",
+        "--dry-run",
+        "--",
+        "mv",
+        "{}",
+        "{}.bak",
+    ]);
+    cmd.assert().success().stdout(predicate::eq(
+        "'mv' 'test' 'test.bak'
+'mv' 'test'\"'\"'test' 'test'\"'\"'test.bak'
+'mv' 'テスト'$'\\n''🦀' 'テスト'$'\\n''🦀.bak'
+"
+        .as_bytes(),
+    ));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("test\0test test\0-rf\0").args([
+        "exec",
+        "--dry-run",
+        "--batch",
+        "--",
+        "ls",
+        "-l",
+    ]);
+    cmd.assert().success().stdout(predicate::eq(
+        "'ls' '-l' 'test' 'test test' '-rf'\n".as_bytes(),
+    ));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn exec_command_for_each_line() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let mut input = Vec::<String>::new();
+    for i in 0..100 {
+        input.push(format!("{}/{:03} 'test'", dir.path().to_str().unwrap(), i));
+    }
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin(input.join("\0"))
+        .args(["exec", "-P", "4", "-b", "7", "--", "touch"]);
+    cmd.assert().success();
+
+    let mut names: Vec<String> = std::fs::read_dir(dir.path())?
+        .map(|v| v.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    let ex: Vec<String> = (0..100).map(|i| format!("{:03} 'test'", i)).collect();
+    assert_eq!(names, ex);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn exec_commands_in_parallel_within_bulk() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a\0b\0c\0d\0")
+        .args(["exec", "-P", "4", "--", "sh", "-c", "sleep 1", "x"]);
+    let start = std::time::Instant::now();
+    cmd.assert().success();
+    assert!(start.elapsed() < std::time::Duration::from_millis(3000));
+    Ok(())
+}
+
+#[test]
+fn exec_batch_across_bulks() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a\0b\0c\0")
+        .args(["exec", "--batch", "--dry-run", "-b", "1", "--", "echo"]);
+    cmd.assert().success().stdout("'echo' 'a' 'b' 'c'\n");
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn exec_fails_when_command_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("test\0test\0")
+        .args(["exec", "--", "false"]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "2 command(s) exited with non-zero status",
+    ));
+    Ok(())
+}
+
+#[test]
+fn protect_leading_dash() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("-rf\0test\0/-rf\0")
+        .args(["--safe-leading-dash", "prefix-dot-slash"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::eq("'./-rf'\n'test'\n'/-rf'\n".as_bytes()));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("-rf\0test\0")
+        .args(["--safe-leading-dash", "warn"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::eq("'-rf'\n'test'\n".as_bytes()))
+        .stderr(predicate::str::contains("\"-rf\" begins with a dash"));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("test\0-rf\0")
+        .args(["--safe-leading-dash", "error"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("\"-rf\" begins with a dash"));
+    Ok(())
+}
+
+#[test]
+fn exec_with_end_of_options() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("-rf\0test\0").args([
+        "--safe-leading-dash",
+        "prefix-dot-slash",
+        "exec",
+        "--dry-run",
+        "--end-of-options",
+        "--",
+        "mv",
+        "-f",
+        "{}",
+        "/tmp",
+    ]);
+    cmd.assert().success().stdout(predicate::eq(
+        "'mv' '-f' '--' './-rf' '/tmp'\n'mv' '-f' '--' 'test' '/tmp'\n".as_bytes(),
+    ));
+    Ok(())
+}
+
+#[test]
+fn audit_lines() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("test\0-rf *\0test\ntest\0テスト🦀\0");
+    cmd.arg("audit");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::starts_with(
+            "1\tleading-dash,glob\t\"-rf *\"\n2\tnewline\t\"test\\ntest\"\nrecords: 4\n",
+        ))
+        .stderr(predicate::str::contains(
+            "audit failed: newline: 1 > 0, leading-dash: 1 > 0, glob: 1 > 0",
+        ));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("test\0test test\0");
+    cmd.arg("audit");
+    cmd.assert().success();
+    Ok(())
+}
+
+#[test]
+fn audit_lines_in_json() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("test\0-rf *\0");
+    cmd.args([
+        "audit",
+        "--format",
+        "json",
+        "--max",
+        "leading-dash=1",
+        "--max",
+        "glob=1",
+    ]);
+    let a = cmd.assert().success();
+    let report: serde_json::Value = serde_json::from_slice(&a.get_output().stdout)?;
+    assert_eq!(report["records"], 2);
+    assert_eq!(report["counts"]["glob"], 1);
+    assert_eq!(report["counts"]["newline"], 0);
+    assert_eq!(report["findings"][0]["index"], 1);
+    assert_eq!(report["findings"][0]["record"], "-rf *");
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn verify_quoted_lines() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("test test\0'test'\0$HOME\0test\ntest\0テスト🦀\0");
+    cmd.args(["--verify", "-b", "2"]);
+    cmd.assert()
+        .success()
+        .stdout("'test test'\n''\"'\"'test'\"'\"''\n'$HOME'\n'test'$'\\n''test'\n'テスト🦀'\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("it's\0");
+    cmd.args(["--dialect", "fish", "--verify=bash"]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "--verify must use a shell of the --dialect",
+    ));
+    Ok(())
+}
+
+#[test]
+fn normalize_lines() -> Result<(), Box<dyn std::error::Error>> {
+    let nfc = "\u{30ac}\u{30a4}\u{30c9}";
+    let nfd = "\u{30ab}\u{3099}\u{30a4}\u{30c8}\u{3099}";
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin(format!("{}\0{}\0", nfd, nfc));
+    cmd.args(["--normalize", "nfc"]);
+    cmd.assert()
+        .success()
+        .stdout(format!("'{}'\n'{}'\n", nfc, nfc));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin(format!("{}\0", nfd));
+    cmd.assert().success().stdout(format!("'{}'\n", nfd));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin(format!("{}\0{}\0", nfd, nfc));
+    cmd.args(["--normalize", "nfc", "audit"]);
+    cmd.assert().success().stdout(
+        predicate::str::contains("0\tnot-normalized\t")
+            .and(predicate::str::contains("not-normalized: 1\n")),
+    );
+    Ok(())
+}
+
+#[test]
+fn convert_encodings() -> Result<(), Box<dyn std::error::Error>> {
+    // "テスト" in Shift_JIS and EUC-JP.
+    let sjis: &[u8] = b"\x83\x65\x83\x58\x83\x67";
+    let eucjp: &[u8] = b"\xa5\xc6\xa5\xb9\xa5\xc8";
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin([sjis, b"\0"].concat());
+    cmd.args(["--input-encoding", "shift_jis"]);
+    cmd.assert().success().stdout("'テスト'\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin([sjis, b"\0"].concat());
+    cmd.args([
+        "--input-encoding",
+        "shift_jis",
+        "--output-encoding",
+        "euc-jp",
+    ]);
+    cmd.assert()
+        .success()
+        .stdout([b"'", eucjp, b"'\n"].concat());
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin([sjis, b"\xa0\0"].concat());
+    cmd.args(["--input-encoding", "shift_jis"]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "could not decode line as Shift_JIS",
+    ));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin([sjis, b"\xa0\0"].concat());
+    cmd.args(["--input-encoding", "shift_jis", "--unmappable", "escape"]);
+    cmd.assert().success().stdout("'テスト'$'\\xa0'\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("テスト🦀\0");
+    cmd.args(["--output-encoding", "euc-jp", "--unmappable", "replace"]);
+    cmd.assert()
+        .success()
+        .stdout([b"'", eucjp, b"?'\n"].concat());
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("テスト🦀\0");
+    cmd.args(["--output-encoding", "euc-jp", "--unmappable", "escape"]);
+    cmd.assert()
+        .success()
+        .stdout([b"'", eucjp, b"'$'\\xf0\\x9f\\xa6\\x80'''\n"].concat());
+
+    for args in [
+        ["--format=quote", "--dialect=fish"],
+        ["--format=heredoc", "--dialect=bash"],
+        ["--format=env", "--env-style=dotenv"],
+    ] {
+        let mut cmd = Command::cargo_bin("xquo")?;
+        cmd.write_stdin("A=テスト🦀\0");
+        cmd.args(["--output-encoding=euc-jp", "--unmappable=escape"]);
+        cmd.args(args);
+        cmd.assert().failure().stderr(predicate::str::contains(
+            "--unmappable escape with --output-encoding can only be used for words quoted for bash",
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn read_defaults_from_config_and_env() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    std::fs::create_dir(dir.path().join("xquo"))?;
+    std::fs::write(
+        dir.path().join("xquo").join("config.toml"),
+        "out-delimiter = \"null\"\nworkers = 4\n\n[profiles.raw]\nno-escape = true\n",
+    )?;
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.env("XDG_CONFIG_HOME", dir.path());
+    cmd.write_stdin("test\ntest\0");
+    cmd.assert().success().stdout("'test'$'\\n''test'\0");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.env("XDG_CONFIG_HOME", dir.path());
+    cmd.env("XQUO_OUT_DELIMITER", "lf");
+    cmd.write_stdin("test\ntest\0");
+    cmd.args(["--profile", "raw"]);
+    cmd.assert().success().stdout("'test\ntest'\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.env("XDG_CONFIG_HOME", dir.path());
+    cmd.env("XQUO_WORKERS", "2");
+    cmd.args(["-o", "lf", "config", "show"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "out-delimiter = \"lf\" # command line\n",
+        ))
+        .stdout(predicate::str::contains("workers = 2 # env XQUO_WORKERS\n"))
+        .stdout(predicate::str::contains("no-escape = false # default\n"));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.env("XDG_CONFIG_HOME", dir.path());
+    cmd.args(["--profile", "unknown", "config", "show"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("profile unknown is not defined"));
+    Ok(())
+}
+
+#[test]
+fn override_config_from_command_line() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    std::fs::create_dir(dir.path().join("xquo"))?;
+    std::fs::write(
+        dir.path().join("xquo").join("config.toml"),
+        "format = \"env\"\nno-escape = true\n",
+    )?;
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.env("XDG_CONFIG_HOME", dir.path());
+    cmd.write_stdin("A=test\ntest\0");
+    cmd.assert().success().stdout("export A='test\ntest'\n");
+
+    // 設定された --format は --assign と衝突せず、使われない.
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.env("XDG_CONFIG_HOME", dir.path());
+    cmd.write_stdin("test\ntest\0");
+    cmd.args(["--assign", "X", "--no-escape=false"]);
+    cmd.assert().success().stdout("X='test'$'\\n''test'\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.env("XDG_CONFIG_HOME", dir.path());
+    cmd.env("XQUO_WORKERS", "many");
+    cmd.write_stdin("test\0");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "invalid value \"many\" for workers from env XQUO_WORKERS",
+    ));
+    Ok(())
+}
+
+#[test]
+fn generate_completions_and_man() -> Result<(), Box<dyn std::error::Error>> {
+    for shell in ["bash", "zsh", "fish", "powershell", "elvish"] {
+        let mut cmd = Command::cargo_bin("xquo")?;
+        cmd.args(["completions", shell]);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("quote-args"));
+    }
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.arg("man");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(".TH xquo 1"));
+    Ok(())
+}
+
+#[test]
+fn quote_lines_for_fish() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("test'test\\\0test\ntest\0");
+    cmd.args(["--dialect", "fish"]);
+    cmd.assert()
+        .success()
+        .stdout("'test\\'test\\\\'\n'test'\\n'test'\n");
+    Ok(())
+}
+
+#[test]
+fn quote_lines_for_fish_and_convert_back() -> Result<(), Box<dyn std::error::Error>> {
+    let input = "it's\\ a\tb\x1b\0x\ny\0$HOME *\0";
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin(input);
+    let bash = cmd.assert().success().get_output().stdout.clone();
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin(input);
+    cmd.args(["--dialect", "fish", "-o", "null"]);
+    let fish = cmd.assert().success().get_output().stdout.clone();
+
+    // fish で quote した行を bash の quote に変換すると、bash で quote した行と一致する.
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin(fish);
+    cmd.args(["convert", "--from", "fish", "--to", "bash"]);
+    cmd.assert().success().stdout(bash);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn init_bash_saves_quoted_words() -> Result<(), Box<dyn std::error::Error>> {
+    let bin = assert_cmd::cargo::cargo_bin("xquo");
+    let path = format!(
+        "{}:{}",
+        bin.parent().unwrap().display(),
+        std::env::var("PATH")?
+    );
+    let mut cmd = std::process::Command::new("bash");
+    cmd.env("PATH", path);
+    cmd.args([
+        "-c",
+        "eval \"$(xquo init bash)\" && xqs printf 'a b\\nc\\n' >/dev/null && printf '%s' \"$__xquo_saved\"",
+    ]);
+    cmd.assert().success().stdout("'a b' 'c' ");
+
+    for shell in ["zsh", "fish"] {
+        let mut cmd = Command::cargo_bin("xquo")?;
+        cmd.args(["init", shell]);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("__xquo_insert_files"));
+    }
+    Ok(())
+}
+
+#[test]
+fn repl_quotes_typed_lines() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a b\n:dialect fish\nit's\n:quit\nnot read\n");
+    cmd.arg("repl");
+    cmd.assert().success().stdout(predicate::str::ends_with(
+        "'a b'\ndialect: fish\n'it\\'s'\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn colorize_quoted_lines() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("test'test\0test\ntest\0");
+    cmd.env("NO_COLOR", "1");
+    cmd.args(["--color=always"]);
+    cmd.assert()
+        .success()
+        .stdout("'test\x1b[33m'\"'\"'\x1b[0mtest'\n'test\x1b[36m'$'\\n''\x1b[0mtest'\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("test'test\0");
+    cmd.assert().success().stdout("'test'\"'\"'test'\n");
+    Ok(())
+}
+
+#[test]
+fn explain_quoted_word() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.args(["explain", "'test'\"'\"'test'$'\\n'"]);
+    cmd.assert().success().stdout(
+        r#"    0..6     single-quoted  'test'           "test"
+    6..9     double-quoted  "'"              "'"
+    9..15    single-quoted  'test'           "test"
+   15..20    ansi-c         $'\n'            "\n"
+result: "test'test\n"
+"#,
+    );
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.args(["explain", "$HOME/*"]);
+    cmd.assert().success().stdout(predicate::str::contains(
+        "warning: unquoted expansion $HOME at byte 0\nwarning: unquoted glob * at byte 6\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn split_command_lines() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("ls -l 'a b' | grep $'\\t'\0echo \"it's\"\n");
+    cmd.arg("split");
+    cmd.assert()
+        .success()
+        .stdout("ls\0-l\0a b\0grep\0\t\0echo\0it's\0")
+        .stderr("xquo: warning: operator \"|\" in line 1 is not a word\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("echo $HOME");
+    cmd.arg("split");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "could not resolve $HOME in $HOME at byte 5",
+    ));
+
+    // 行全体ではなく、分けた word を正規化する. - で始まる word はそのまま出力する.
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("-x ＇a b＇\0");
+    cmd.args([
+        "--safe-leading-dash",
+        "prefix-dot-slash",
+        "--normalize",
+        "nfkc",
+        "split",
+    ]);
+    cmd.assert().success().stdout("-x\0'a\0b'\0");
+    Ok(())
+}
+
+#[test]
+fn convert_bash_to_fish() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("echo 'it'\"'\"'s' >out 2>&1\0");
+    cmd.args(["convert", "--from", "bash", "--to", "fish"]);
+    cmd.assert()
+        .success()
+        .stdout("'echo' 'it\\'s' > 'out' 2>&1\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("cat <(ls)\0");
+    cmd.args(["convert", "--to", "fish"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("could not convert line 1"));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("echo {a,b}\0");
+    cmd.args(["convert", "--to", "fish"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("could not resolve {a,b}"));
+    Ok(())
+}
+
+// exec の途中では /proc/PID/cmdline が空のことがあるので、program が起動するまで待つ.
+#[cfg(target_os = "linux")]
+fn wait_for_program(
+    child: &std::process::Child,
+    program: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let pid = child.id().to_string();
+    for _ in 0..100 {
+        let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid))?;
+        if cmdline.starts_with(program.as_bytes()) {
+            return Ok(pid);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    Err(format!("{} did not start", program).into())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn print_command_line_of_process() -> Result<(), Box<dyn std::error::Error>> {
+    let mut child = std::process::Command::new("/bin/sh")
+        .args(["-c", "sleep 10; :", "it's"])
+        .env_clear()
+        .env("XQUO_TEST", "a b")
+        .spawn()?;
+    let pid = wait_for_program(&child, "/bin/sh")?;
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.args(["pid", &pid]);
+    let cmdline = cmd.assert();
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.args(["--dialect", "fish", "pid", "--environ", &pid]);
+    let environ = cmd.assert();
+
+    child.kill()?;
+    child.wait()?;
+    cmdline
+        .success()
+        .stdout("'/bin/sh' '-c' 'sleep 10; :' 'it'\"'\"'s'\n");
+    environ.success().stdout("set -gx XQUO_TEST 'a b'\n");
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn skip_invalid_names_in_environ() -> Result<(), Box<dyn std::error::Error>> {
+    let mut child = std::process::Command::new("/bin/bash")
+        .args(["-c", "f() { :; }; export -f f; exec sleep 10"])
+        .env_clear()
+        .env("XQUO_TEST", "a b")
+        .spawn()?;
+    let pid = wait_for_program(&child, "sleep")?;
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.args(["pid", "--environ", &pid]);
+    let environ = cmd.assert();
+
+    child.kill()?;
+    child.wait()?;
+    environ
+        .success()
+        .stdout(predicate::str::contains("export XQUO_TEST='a b'\n"))
+        .stdout(predicate::str::contains("BASH_FUNC").not())
+        .stderr(predicate::str::contains(
+            "xquo: warning: \"BASH_FUNC_f%%\" is not a valid variable name, skipped",
+        ));
+    Ok(())
+}
+
+#[test]
+fn quote_lines_as_env() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("A=it's\0B=a=b\0");
+    cmd.args(["--format", "env"]);
+    cmd.assert()
+        .success()
+        .stdout("export A='it'\"'\"'s'\nexport B='a=b'\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("A=it's\0");
+    cmd.args(["--format=env", "--dialect=fish"]);
+    cmd.assert().success().stdout("set -gx A 'it\\'s'\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("A=it's\0B=$x\0");
+    cmd.args(["--format=env", "--env-style=dotenv"]);
+    cmd.assert().success().stdout("A=\"it's\"\nB='$x'\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("A=it's\0");
+    cmd.args(["--format=env", "--env-style=docker"]);
+    cmd.assert().success().stdout("A=it's\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("1A=x\0");
+    cmd.args(["--format=env"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("is not a valid variable name"));
+    Ok(())
+}
+
+#[test]
+fn quote_lines_as_assignments() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a b\0it's\0");
+    cmd.args(["--assign", "X", "--assign-prefix", "readonly"]);
+    cmd.assert()
+        .success()
+        .stdout("readonly X='a b'\nreadonly X='it'\"'\"'s'\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a\0b\0c\0");
+    cmd.args(["--assign-indexed", "X", "--bulk-lines", "2"]);
+    cmd.assert()
+        .success()
+        .stdout("X[0]='a'\nX[1]='b'\nX[2]='c'\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a\0b\0");
+    cmd.args(["--dialect", "fish", "--assign-indexed", "X"]);
+    cmd.assert().success().stdout("set X 'a'\nset -a X 'b'\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a\0b\0c\0d\0");
+    cmd.args(["--assign-indexed", "X", "-b", "1", "-w", "4"]);
+    cmd.assert()
+        .success()
+        .stdout("X[0]='a'\nX[1]='b'\nX[2]='c'\nX[3]='d'\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a\0b\0c\0d\0");
+    cmd.args(["--assign-indexed", "X", "-b", "1", "-w", "4", "--unordered"]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let mut lines: Vec<&str> = from_utf8(&output)?.lines().collect();
+    lines.sort();
+    assert_eq!(lines, ["X[0]='a'", "X[1]='b'", "X[2]='c'", "X[3]='d'"]);
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a\0b\0");
+    cmd.args(["--dialect", "fish", "--assign-indexed", "X", "--unordered"]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "--assign-indexed for fish cannot be used with --unordered",
+    ));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a\0");
+    cmd.args(["--assign-prefix", "local"]);
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn quote_lines_in_heredoc() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("it's $HOME\0XQUO_EOF\0a\nb\0");
+    cmd.args(["--format", "heredoc"]);
+    cmd.assert()
+        .success()
+        .stdout("<<'XQUO_EOF_1'\nit's $HOME\nXQUO_EOF\n'a'$'\\n''b'\nXQUO_EOF_1\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a\nb\0");
+    cmd.args(["--format", "heredoc", "--no-escape"]);
+    cmd.assert()
+        .success()
+        .stdout("<<'XQUO_EOF'\n'a'$'\\n''b'\nXQUO_EOF\n");
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a\0");
+    cmd.args(["--format", "heredoc", "-o", "null"]);
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn quote_lines_in_script() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a b\0c\0");
+    cmd.args(["--script", "--assign-indexed", "X", "--bulk-lines", "1"]);
+    cmd.assert().success().stdout(format!(
+        "#!/usr/bin/env bash
+# Generated by xquo {}
+# Options: '--script' '--assign-indexed' 'X' '--bulk-lines' '1'
+# Records: 2
+set -euo pipefail
+
+X[0]='a b'
+X[1]='c'
+",
+        env!("CARGO_PKG_VERSION")
+    ));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.args(["--confirm"]);
+    cmd.assert().failure();
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("rm\0");
+    cmd.args(["--script"]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "--script needs --assign, --assign-indexed or --format env with --env-style shell",
+    ));
+
+    for args in [
+        ["--format", "env", "--env-style", "docker"].as_slice(),
+        &["--format", "env", "--env-style", "dotenv"],
+        &["--format", "heredoc"],
+    ] {
+        let mut cmd = Command::cargo_bin("xquo")?;
+        cmd.write_stdin("A=x; rm -rf ~\0");
+        cmd.args(["--script"]).args(args);
+        cmd.assert()
+            .failure()
+            .stdout("")
+            .stderr(predicate::str::contains(
+                "--script needs --assign, --assign-indexed or --format env with --env-style shell",
+            ));
+    }
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a\0");
+    cmd.args(["--script", "--assign", "X", "--assign-prefix", "local"]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "--script cannot use --assign-prefix local",
+    ));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("A=x; rm -rf ~\0");
+    cmd.args(["--script", "--format", "env"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::ends_with("\nexport A='x; rm -rf ~'\n"));
+
+    let mut cmd = Command::cargo_bin("xquo")?;
+    cmd.write_stdin("a\0");
+    cmd.args(["--script", "--assign", "X", "-o", "null"]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "scripts need lines delimited by lf",
+    ));
+    Ok(())
+}
+
 //#[test]
 // fn file_doesnt_exist() -> Result<(), Box<dyn std::error::Error>> {
 //     let mut cmd = Command::cargo_bin("xquo")?;